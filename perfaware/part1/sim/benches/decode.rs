@@ -0,0 +1,103 @@
+// run with `cargo +nightly bench`. the inputs are the course listings, so the
+// numbers are comparable between runs and machines.
+//
+// this is libtest's bench harness rather than criterion because the crate has
+// no dependencies and already needs nightly.
+//
+// libtest only knows throughput in bytes, so `b.bytes` is set to the number
+// of instructions instead: the MB/s it prints is millions of instructions a
+// second
+#![feature(test)]
+
+extern crate test;
+
+use std::fmt::Write;
+
+use sim::{decode, decode_first_at, DecodeCache, CPU};
+use test::{black_box, Bencher};
+
+fn read_listing(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+// every listing that decodes, over and over until there's a good 64K of it
+fn decode_input() -> Vec<u8> {
+    let listings = [
+        "listing_0039_more_movs",
+        "listing_0040_challenge_movs",
+        "listing_0041_add_sub_cmp_jnz",
+        "listing_0046_add_sub_cmp",
+        "listing_0049_conditional_jumps",
+        "listing_0054_draw_rectangle",
+        "listing_0056_estimating_cycles",
+    ]
+    .map(read_listing)
+    .concat();
+    listings.repeat(64 * 1024 / listings.len())
+}
+
+#[bench]
+fn decode_listings(b: &mut Bencher) {
+    let bytes = decode_input();
+    b.bytes = decode(&bytes).unwrap().len() as u64;
+    b.iter(|| decode(black_box(&bytes)).unwrap());
+}
+
+// printing what's already decoded, into one buffer like the output
+#[bench]
+fn format_listings(b: &mut Bencher) {
+    let insts = decode(&decode_input()).unwrap();
+    b.bytes = insts.len() as u64;
+    let mut out = String::new();
+    b.iter(|| {
+        out.clear();
+        for inst in &insts {
+            writeln!(out, "{inst}").unwrap();
+        }
+        black_box(out.len())
+    });
+}
+
+// how many instructions the program runs before it falls off the end
+fn executed(program: &[u8]) -> u64 {
+    let mut cpu = CPU::new();
+    let mut count = 0;
+    while (cpu.ip() as usize) < program.len() {
+        let (inst, num_bytes) = decode_first_at(program, cpu.ip() as usize).unwrap();
+        cpu.exec(inst, num_bytes).unwrap();
+        count += 1;
+    }
+    count
+}
+
+#[bench]
+fn sim_draw_rectangle(b: &mut Bencher) {
+    // 64x64 pixels, about 29K instructions
+    let program = read_listing("listing_0054_draw_rectangle");
+    b.bytes = executed(&program);
+    b.iter(|| {
+        let mut cpu = CPU::new();
+        while (cpu.ip() as usize) < program.len() {
+            let (inst, num_bytes) = decode_first_at(&program, cpu.ip() as usize).unwrap();
+            cpu.exec(inst, num_bytes).unwrap();
+        }
+        cpu
+    });
+}
+
+// same again, decoding each instruction once instead of every time round the
+// loop
+#[bench]
+fn sim_draw_rectangle_cached(b: &mut Bencher) {
+    let program = read_listing("listing_0054_draw_rectangle");
+    b.bytes = executed(&program);
+    b.iter(|| {
+        let mut cpu = CPU::new();
+        let mut cache = DecodeCache::new(program.len());
+        while (cpu.ip() as usize) < program.len() {
+            let (inst, num_bytes) = cache.decode_at(&program, cpu.ip() as usize).unwrap();
+            cpu.exec(inst, num_bytes).unwrap();
+        }
+        cpu
+    });
+}
@@ -0,0 +1,443 @@
+// the other way around from decode.rs, for the subset of nasm that the
+// decoder writes out: mov, the eight binops and the short jumps, with
+// register, memory and immediate operands. it picks the same encodings nasm
+// does, so a disassembly assembles back to the bytes it came from
+use crate::instruction::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AsmError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    // operands that don't parse, or that the instruction has no form for
+    BadOperands { line: usize, text: String },
+    // an immediate too big for its operand, or a jump too far for a byte
+    OutOfRange { line: usize, text: String },
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {line}: unknown instruction {mnemonic}")
+            }
+            Self::BadOperands { line, text } => write!(f, "line {line}: bad operands in {text}"),
+            Self::OutOfRange { line, text } => write!(f, "line {line}: out of range in {text}"),
+        }
+    }
+}
+
+pub fn assemble(asm: &str) -> Result<Vec<u8>, AsmError> {
+    let mut bytes = vec![];
+    for (i, line) in asm.lines().enumerate() {
+        // the decoder's `; -> 0x10` notes on jumps are comments too
+        let text = line.split(';').next().unwrap_or_default().trim();
+        if text.is_empty() || text.starts_with("bits ") || text.starts_with("org ") {
+            continue;
+        }
+        let op = parse_line(text).map_err(|err| err.at(i + 1, text))?;
+        encode(&op, &mut bytes);
+    }
+    Ok(bytes)
+}
+
+// what went wrong on a line, before it's known which line
+enum LineError {
+    UnknownMnemonic,
+    BadOperands,
+    OutOfRange,
+}
+
+impl LineError {
+    fn at(self, line: usize, text: &str) -> AsmError {
+        match self {
+            Self::UnknownMnemonic => AsmError::UnknownMnemonic {
+                line,
+                mnemonic: text.split_whitespace().next().unwrap_or_default().into(),
+            },
+            Self::BadOperands => AsmError::BadOperands {
+                line,
+                text: text.into(),
+            },
+            Self::OutOfRange => AsmError::OutOfRange {
+                line,
+                text: text.into(),
+            },
+        }
+    }
+}
+
+fn parse_line(text: &str) -> Result<Op, LineError> {
+    let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let mnemonic = mnemonic.to_lowercase();
+
+    if let Some(typ) = JumpType::ALL.iter().find(|typ| typ.asm() == mnemonic) {
+        return parse_jump(*typ, operands.trim());
+    }
+
+    let make: fn(Loc, Loc) -> Op = match mnemonic.as_str() {
+        "mov" => |dst, src| Op::Mov(Mov { dst, src }),
+        "add" => |dst, src| Op::Add(Add { dst, src }),
+        "or" => |dst, src| Op::Or(Or { dst, src }),
+        "adc" => |dst, src| Op::Adc(Adc { dst, src }),
+        "sbb" => |dst, src| Op::Sbb(Sbb { dst, src }),
+        "and" => |dst, src| Op::And(And { dst, src }),
+        "sub" => |dst, src| Op::Sub(Sub { dst, src }),
+        "xor" => |dst, src| Op::Xor(Xor { dst, src }),
+        "cmp" => |dst, src| Op::Cmp(Cmp { dst, src }),
+        _ => return Err(LineError::UnknownMnemonic),
+    };
+    let (dst, src) = operands.split_once(',').ok_or(LineError::BadOperands)?;
+    let (dst, src) = parse_operands(parse_operand(dst)?, parse_operand(src)?)?;
+    // segment registers only go with mov
+    let has_sreg = [dst, src]
+        .iter()
+        .any(|loc| matches!(loc, Loc::Reg(reg) if is_sreg(*reg)));
+    if has_sreg && mnemonic != "mov" {
+        return Err(LineError::BadOperands);
+    }
+    Ok(make(dst, src))
+}
+
+// `$+4` or `$-2`, from the start of the jump like nasm has it
+fn parse_jump(typ: JumpType, operand: &str) -> Result<Op, LineError> {
+    let offset = operand
+        .strip_prefix('$')
+        .and_then(parse_signed)
+        .ok_or(LineError::BadOperands)?;
+    let jump = Jump { typ, offset: 0 };
+    let offset = i8::try_from(offset - jump.size() as i32).map_err(|_| LineError::OutOfRange)?;
+    Ok(Op::Jump(Jump { typ, offset }))
+}
+
+enum Operand {
+    Reg(RegIndex),
+    // with the size if it was given, like `byte [bx]`
+    Mem(EAC, Option<Width>),
+    Imm(i32, Option<Width>),
+}
+
+fn parse_operand(text: &str) -> Result<Operand, LineError> {
+    let text = text.trim();
+    let (width, text) = match text.split_once(' ') {
+        Some(("byte", rest)) => (Some(Width::Byte), rest.trim()),
+        Some(("word", rest)) => (Some(Width::Word), rest.trim()),
+        _ => (None, text),
+    };
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return Ok(Operand::Mem(parse_eac(inner)?, width));
+    }
+    if let Some(reg) = RegIndex::from_mnemonic(text) {
+        if width.is_some() || reg.register == Reg::IP {
+            return Err(LineError::BadOperands);
+        }
+        return Ok(Operand::Reg(reg));
+    }
+    let n = parse_signed(text).ok_or(LineError::BadOperands)?;
+    Ok(Operand::Imm(n, width))
+}
+
+// the inside of the brackets, like `es:bx + si - 4`
+fn parse_eac(text: &str) -> Result<EAC, LineError> {
+    let (segment, text) = match text.split_once(':') {
+        Some((segment, rest)) => {
+            let reg = RegIndex::from_mnemonic(segment.trim()).filter(|reg| is_sreg(*reg));
+            (Some(reg.ok_or(LineError::BadOperands)?.register), rest)
+        }
+        None => (None, text),
+    };
+
+    let mut regs = vec![];
+    let mut displacement = None;
+    let mut sign = 1;
+    for term in text
+        .replace('+', " + ")
+        .replace('-', " - ")
+        .split_whitespace()
+    {
+        match term {
+            "+" => sign = 1,
+            "-" => sign = -1,
+            term => {
+                if let Some(reg) = RegIndex::from_mnemonic(term) {
+                    if reg.region != Region::Xtended {
+                        return Err(LineError::BadOperands);
+                    }
+                    regs.push(reg.register);
+                } else {
+                    let n = parse_signed(term).ok_or(LineError::BadOperands)?;
+                    displacement = Some(displacement.unwrap_or(0) + sign * n);
+                }
+            }
+        }
+    }
+
+    use EABase::*;
+    let base = match regs[..] {
+        [] => {
+            let addr = displacement.ok_or(LineError::BadOperands)?;
+            let addr = u16::try_from(addr).map_err(|_| LineError::OutOfRange)?;
+            return Ok(EAC {
+                base: DirectAddr(addr),
+                displacement: None,
+                segment,
+            });
+        }
+        [Reg::B, Reg::SI] | [Reg::SI, Reg::B] => BxSi,
+        [Reg::B, Reg::DI] | [Reg::DI, Reg::B] => BxDi,
+        [Reg::BP, Reg::SI] | [Reg::SI, Reg::BP] => BpSi,
+        [Reg::BP, Reg::DI] | [Reg::DI, Reg::BP] => BpDi,
+        [Reg::SI] => Si,
+        [Reg::DI] => Di,
+        [Reg::B] => Bx,
+        [Reg::BP] => Bp,
+        _ => return Err(LineError::BadOperands),
+    };
+    let displacement = match displacement {
+        Some(d) => Some(i16::try_from(d).map_err(|_| LineError::OutOfRange)?),
+        // [bp] on its own is how a direct address is encoded, so it's
+        // always written with a 0 displacement
+        None if base == Bp => Some(0),
+        None => None,
+    };
+    Ok(EAC {
+        base,
+        displacement,
+        segment,
+    })
+}
+
+// the size comes from a register on either side, or from a keyword when
+// there isn't one
+fn parse_operands(dst: Operand, src: Operand) -> Result<(Loc, Loc), LineError> {
+    let width_of = |op: &Operand| match op {
+        Operand::Reg(reg) => Some(reg.region.width()),
+        Operand::Mem(_, width) | Operand::Imm(_, width) => *width,
+    };
+    let width = match (width_of(&dst), width_of(&src)) {
+        (Some(a), Some(b)) if a != b => return Err(LineError::BadOperands),
+        (a, b) => a.or(b).ok_or(LineError::BadOperands)?,
+    };
+    let loc = |op: Operand| -> Result<Loc, LineError> {
+        Ok(match op {
+            Operand::Reg(reg) => Loc::Reg(reg),
+            Operand::Mem(eac, _) => Loc::EAC(eac),
+            Operand::Imm(n, _) => match width {
+                Width::Byte if (-0x80..=0xff).contains(&n) => Loc::Imm8(n as u8),
+                Width::Word if (-0x8000..=0xffff).contains(&n) => Loc::Imm16(n as u16),
+                _ => return Err(LineError::OutOfRange),
+            },
+        })
+    };
+    match (loc(dst)?, loc(src)?) {
+        (Loc::Imm8(_) | Loc::Imm16(_), _) | (Loc::EAC(_), Loc::EAC(_)) => {
+            Err(LineError::BadOperands)
+        }
+        (Loc::Reg(reg), Loc::Imm8(_) | Loc::Imm16(_)) if is_sreg(reg) => {
+            Err(LineError::BadOperands)
+        }
+        (Loc::Reg(a), Loc::Reg(b)) if is_sreg(a) && is_sreg(b) => Err(LineError::BadOperands),
+        locs => Ok(locs),
+    }
+}
+
+// decimal or 0x hex, with an optional minus in front
+fn parse_signed(text: &str) -> Option<i32> {
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let n = match text.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    Some(sign * n)
+}
+
+fn is_sreg(reg: RegIndex) -> bool {
+    matches!(reg.register, Reg::ES | Reg::CS | Reg::SS | Reg::DS)
+}
+
+fn encode(op: &Op, out: &mut Vec<u8>) {
+    match op {
+        Op::Mov(Mov { dst, src }) => encode_mov(*dst, *src, out),
+        Op::Add(Add { dst, src }) => encode_binop(0b000, *dst, *src, out),
+        Op::Or(Or { dst, src }) => encode_binop(0b001, *dst, *src, out),
+        Op::Adc(Adc { dst, src }) => encode_binop(0b010, *dst, *src, out),
+        Op::Sbb(Sbb { dst, src }) => encode_binop(0b011, *dst, *src, out),
+        Op::And(And { dst, src }) => encode_binop(0b100, *dst, *src, out),
+        Op::Sub(Sub { dst, src }) => encode_binop(0b101, *dst, *src, out),
+        Op::Xor(Xor { dst, src }) => encode_binop(0b110, *dst, *src, out),
+        Op::Cmp(Cmp { dst, src }) => encode_binop(0b111, *dst, *src, out),
+        Op::Jump(jump) => out.extend([jump.typ as u8, jump.offset as u8]),
+        _ => unreachable!("parse_line only makes the instructions above"),
+    }
+}
+
+fn encode_mov(dst: Loc, src: Loc, out: &mut Vec<u8>) {
+    let w = w_bit(dst, src);
+    encode_prefix(dst, src, out);
+    match (dst, src) {
+        (Loc::Reg(reg), Loc::Imm8(_) | Loc::Imm16(_)) => {
+            out.push(0b_1011_0000 | w << 3 | reg_code(reg));
+            encode_imm(src, out);
+        }
+        (_, Loc::Imm8(_) | Loc::Imm16(_)) => {
+            out.push(0b_1100_0110 | w);
+            encode_mod_rm(0, dst, out);
+            encode_imm(src, out);
+        }
+        (
+            Loc::Reg(reg),
+            Loc::EAC(EAC {
+                base: EABase::DirectAddr(addr),
+                ..
+            }),
+        ) if reg.is_acc() => {
+            out.push(0b_1010_0000 | w);
+            out.extend(addr.to_le_bytes());
+        }
+        (
+            Loc::EAC(EAC {
+                base: EABase::DirectAddr(addr),
+                ..
+            }),
+            Loc::Reg(reg),
+        ) if reg.is_acc() => {
+            out.push(0b_1010_0010 | w);
+            out.extend(addr.to_le_bytes());
+        }
+        (Loc::Reg(sreg), r_m) if is_sreg(sreg) => {
+            out.push(0b_1000_1110);
+            encode_mod_rm(reg_code(sreg), r_m, out);
+        }
+        (r_m, Loc::Reg(sreg)) if is_sreg(sreg) => {
+            out.push(0b_1000_1100);
+            encode_mod_rm(reg_code(sreg), r_m, out);
+        }
+        (r_m, Loc::Reg(reg)) => {
+            out.push(0b_1000_1000 | w);
+            encode_mod_rm(reg_code(reg), r_m, out);
+        }
+        (Loc::Reg(reg), r_m) => {
+            out.push(0b_1000_1010 | w);
+            encode_mod_rm(reg_code(reg), r_m, out);
+        }
+        _ => unreachable!("parse_operands rejects the rest"),
+    }
+}
+
+// `op` is the 3 bits that pick the binop, in the opcode for the register
+// forms and in the reg field for the immediate ones
+fn encode_binop(op: u8, dst: Loc, src: Loc, out: &mut Vec<u8>) {
+    let w = w_bit(dst, src);
+    encode_prefix(dst, src, out);
+    match (dst, src) {
+        (Loc::Reg(reg), Loc::Imm8(_) | Loc::Imm16(_)) if reg.is_acc() => {
+            out.push(op << 3 | 0b100 | w);
+            encode_imm(src, out);
+        }
+        // sign extended from a byte
+        (_, Loc::Imm16(n)) if (n as i16) == (n as i8) as i16 => {
+            out.push(0b_1000_0011);
+            encode_mod_rm(op, dst, out);
+            out.push(n as u8);
+        }
+        (_, Loc::Imm8(_) | Loc::Imm16(_)) => {
+            out.push(0b_1000_0000 | w);
+            encode_mod_rm(op, dst, out);
+            encode_imm(src, out);
+        }
+        (r_m, Loc::Reg(reg)) => {
+            out.push(op << 3 | w);
+            encode_mod_rm(reg_code(reg), r_m, out);
+        }
+        (Loc::Reg(reg), r_m) => {
+            out.push(op << 3 | 0b10 | w);
+            encode_mod_rm(reg_code(reg), r_m, out);
+        }
+        _ => unreachable!("parse_operands rejects the rest"),
+    }
+}
+
+fn w_bit(dst: Loc, src: Loc) -> u8 {
+    matches!(Loc::op_width(dst, src), Width::Word) as u8
+}
+
+// 001SR110
+fn encode_prefix(dst: Loc, src: Loc, out: &mut Vec<u8>) {
+    let segment = [dst, src].into_iter().find_map(|loc| match loc {
+        Loc::EAC(eac) => eac.segment,
+        _ => None,
+    });
+    if let Some(segment) = segment {
+        out.push(0b_0010_0110 | sr_code(segment) << 3);
+    }
+}
+
+fn encode_imm(imm: Loc, out: &mut Vec<u8>) {
+    match imm {
+        Loc::Imm8(n) => out.push(n),
+        Loc::Imm16(n) => out.extend(n.to_le_bytes()),
+        _ => unreachable!("not an immediate"),
+    }
+}
+
+// MOD|REG|R/M, then the displacement
+fn encode_mod_rm(reg: u8, r_m: Loc, out: &mut Vec<u8>) {
+    let eac = match r_m {
+        Loc::Reg(r_m) => {
+            out.push(0b_1100_0000 | reg << 3 | reg_code(r_m));
+            return;
+        }
+        Loc::EAC(eac) => eac,
+        Loc::Imm8(_) | Loc::Imm16(_) => unreachable!("an immediate has no mod r/m"),
+    };
+    let r_m = match eac.base {
+        EABase::BxSi => 0b000,
+        EABase::BxDi => 0b001,
+        EABase::BpSi => 0b010,
+        EABase::BpDi => 0b011,
+        EABase::Si => 0b100,
+        EABase::Di => 0b101,
+        EABase::Bp | EABase::DirectAddr(_) => 0b110,
+        EABase::Bx => 0b111,
+    };
+    match (eac.base, eac.displacement) {
+        (EABase::DirectAddr(addr), _) => {
+            out.push(reg << 3 | r_m);
+            out.extend(addr.to_le_bytes());
+        }
+        (_, None) => out.push(reg << 3 | r_m),
+        (_, Some(d)) if d == (d as i8) as i16 => {
+            out.extend([0b_0100_0000 | reg << 3 | r_m, d as u8])
+        }
+        (_, Some(d)) => {
+            out.push(0b_1000_0000 | reg << 3 | r_m);
+            out.extend(d.to_le_bytes());
+        }
+    }
+}
+
+// the 3 bit REG field, or the 2 bit SR one for a segment register
+fn reg_code(reg: RegIndex) -> u8 {
+    match (reg.register, reg.region) {
+        (Reg::A, Region::Low | Region::Xtended) => 0b000,
+        (Reg::C, Region::Low | Region::Xtended) => 0b001,
+        (Reg::D, Region::Low | Region::Xtended) => 0b010,
+        (Reg::B, Region::Low | Region::Xtended) => 0b011,
+        (Reg::A, Region::High) | (Reg::SP, _) => 0b100,
+        (Reg::C, Region::High) | (Reg::BP, _) => 0b101,
+        (Reg::D, Region::High) | (Reg::SI, _) => 0b110,
+        (Reg::B, Region::High) | (Reg::DI, _) => 0b111,
+        (segment, _) => sr_code(segment),
+    }
+}
+
+fn sr_code(segment: Reg) -> u8 {
+    match segment {
+        Reg::ES => 0b00,
+        Reg::CS => 0b01,
+        Reg::SS => 0b10,
+        Reg::DS => 0b11,
+        _ => unreachable!("{segment:?} isn't a segment register"),
+    }
+}
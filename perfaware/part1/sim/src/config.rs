@@ -0,0 +1,444 @@
+use std::ops::Range;
+
+use crate::cpu::{MEMORY_SIZE, SEGMENTED_MEMORY_SIZE};
+use crate::instruction::{RegIndex, Region};
+use crate::syntax::Syntax;
+
+pub const USAGE: &str = "\
+usage: sim [options] [file...]
+
+reads the program from stdin when there's no file, or the file is -
+
+options:
+  --exec                   simulate instead of decoding
+  --trace                  print what each instruction changed (with --exec)
+  --trace-addresses        --trace, plus the physical address of every memory
+                           access
+  --image                  write memory to image.bin at the end (with --exec)
+  --byte-registers         also print al, ah, bl, ... at the end (with --exec)
+  --stats                  count what ran, by mnemonic, and which jumps were
+                           taken (with --exec)
+  --segmented              add segment registers into addresses, with 1MB of
+                           memory (with --exec)
+  --labels                 name jump targets in the disassembly
+  --listing                put the address and bytes in front of each
+                           instruction, like objdump
+  --addresses              add the address of each instruction as a comment,
+                           for matching it up with a hex dump
+  --range <start:end>      only decode the bytes from start up to end, which
+                           has to be where an instruction starts
+  --count                  print how many instructions and bytes there are,
+                           instead of the instructions
+  --syntax <intel|att>     which assembler to write the instructions for,
+                           intel (nasm) by default
+  --skip-unknown           print bytes that don't decode as db and go on,
+                           instead of stopping at the first one
+  --cycle-estimate         estimate 8086 clocks per instruction
+  --cycle-estimate-8088    estimate 8088 clocks per instruction
+  --origin <addr>          address the program is loaded at
+  -o, --output <file>      write the disassembly to a file instead of stdout
+  --set <reg=value>        start with a register set, like --set bx=1000,
+                           can be repeated (with --exec)
+  --stack-floor <addr>     stop if sp goes below this, or wraps (with --exec)
+  --step                   stop before every instruction and wait for enter,
+                           r to print the registers or q to quit (with --exec)
+  --break <addr>           print the registers whenever ip gets to addr, can
+                           be repeated (with --exec)
+  --break-stop             stop at the first breakpoint instead of going on
+  --max-instructions <n>   stop after running n instructions, for programs
+                           that never finish (with --exec)
+  --dump <start:len:file>  write part of memory to a file at the end (with --exec)
+  --render <WxH:start:file>
+                           write the RGBA pixels at start to a PPM image at
+                           the end, can be repeated (with --exec)
+  --verify <file>          check the final registers and flags against a dump
+                           in the same format (with --exec)
+  --help                   print this";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Decode,
+    Simulate,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Config {
+    pub mode: Mode,
+    pub files: Vec<String>, // `-` is stdin
+    pub image: bool,
+    pub byte_registers: bool,
+    pub stats: bool,
+    pub segmented: bool,
+    pub labels: bool,
+    pub listing: bool,
+    pub addresses: bool,
+    pub skip_unknown: bool,
+    pub count: bool,
+    pub syntax: Syntax,
+    pub trace: bool,
+    pub trace_addresses: bool,
+    pub cycle_estimate: bool,
+    pub cycle_estimate_8088: bool,
+    pub origin: u16,
+    pub range: Option<Range<usize>>,
+    pub output: Option<String>,
+    pub registers: Vec<(RegIndex, u16)>,
+    pub stack_floor: Option<u16>,
+    pub max_instructions: Option<usize>,
+    pub breakpoints: Vec<u16>,
+    pub break_stop: bool,
+    pub step: bool,
+    pub dumps: Vec<Dump>,
+    pub renders: Vec<Render>,
+    pub verify: Option<String>,
+    pub help: bool,
+}
+
+// `memory[start..start + len]`, written to `file`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Dump {
+    pub start: usize,
+    pub len: usize,
+    pub file: String,
+}
+
+// `width` x `height` RGBA pixels from `start`, written to `file`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Render {
+    pub width: usize,
+    pub height: usize,
+    pub start: usize,
+    pub file: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownFlag(String),
+    MissingValue(String),
+    BadValue { flag: String, value: String },
+    NeedsExec(String),
+    Conflict(String, String),
+    OutOfRange { start: usize, len: usize },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownFlag(flag) => write!(f, "unknown flag {flag}"),
+            Self::MissingValue(flag) => write!(f, "{flag} needs a value"),
+            Self::BadValue { flag, value } => write!(f, "bad value {value} for {flag}"),
+            Self::NeedsExec(flag) => write!(f, "{flag} only makes sense with --exec"),
+            Self::Conflict(a, b) => write!(f, "{a} can't be used with {b}"),
+            Self::OutOfRange { start, len } => {
+                write!(f, "{len} bytes from {start:#x} goes past the end of memory")
+            }
+        }
+    }
+}
+
+impl Config {
+    // flags take one dash or two, `-exec` is what the course's scripts use
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut config = Self {
+            mode: Mode::Decode,
+            files: vec![],
+            image: false,
+            byte_registers: false,
+            stats: false,
+            segmented: false,
+            labels: false,
+            listing: false,
+            addresses: false,
+            skip_unknown: false,
+            count: false,
+            syntax: Syntax::Intel,
+            trace: false,
+            trace_addresses: false,
+            cycle_estimate: false,
+            cycle_estimate_8088: false,
+            origin: 0,
+            range: None,
+            output: None,
+            registers: vec![],
+            stack_floor: None,
+            max_instructions: None,
+            breakpoints: vec![],
+            break_stop: false,
+            step: false,
+            dumps: vec![],
+            renders: vec![],
+            verify: None,
+            help: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "-" || !arg.starts_with('-') {
+                config.files.push(arg);
+                continue;
+            }
+            let (name, inline_value) = match arg.trim_start_matches('-').split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg.trim_start_matches('-').to_string(), None),
+            };
+            match name.as_str() {
+                "exec" => config.mode = Mode::Simulate,
+                "image" => config.image = true,
+                "byte-registers" => config.byte_registers = true,
+                "stats" => config.stats = true,
+                "segmented" => config.segmented = true,
+                "labels" => config.labels = true,
+                "listing" => config.listing = true,
+                "addresses" => config.addresses = true,
+                "skip-unknown" => config.skip_unknown = true,
+                "count" => config.count = true,
+                "trace" => config.trace = true,
+                "trace-addresses" => {
+                    config.trace = true;
+                    config.trace_addresses = true;
+                }
+                "cycle-estimate" => config.cycle_estimate = true,
+                "cycle-estimate-8088" => config.cycle_estimate_8088 = true,
+                "h" | "help" => config.help = true,
+                "origin" => config.origin = number(&arg, inline_value, &mut args)?,
+                "stack-floor" => config.stack_floor = Some(number(&arg, inline_value, &mut args)?),
+                "set" => {
+                    let value = value(&arg, inline_value, &mut args)?;
+                    config.registers.push(parsed(&arg, value, parse_register)?);
+                }
+                "break-stop" => config.break_stop = true,
+                "step" => config.step = true,
+                "break" => config
+                    .breakpoints
+                    .push(number(&arg, inline_value, &mut args)?),
+                "max-instructions" => {
+                    config.max_instructions = Some(number(&arg, inline_value, &mut args)?);
+                }
+                "syntax" => {
+                    let value = value(&arg, inline_value, &mut args)?;
+                    config.syntax = parsed(&arg, value, |value| match value {
+                        "intel" => Some(Syntax::Intel),
+                        "att" => Some(Syntax::Att),
+                        _ => None,
+                    })?;
+                }
+                "range" => {
+                    let value = value(&arg, inline_value, &mut args)?;
+                    config.range = Some(parsed(&arg, value, parse_range)?);
+                }
+                "o" | "output" => config.output = Some(value(&arg, inline_value, &mut args)?),
+                "verify" => config.verify = Some(value(&arg, inline_value, &mut args)?),
+                "dump" => {
+                    let value = value(&arg, inline_value, &mut args)?;
+                    config.dumps.push(parsed(&arg, value, parse_dump)?);
+                }
+                "render" => {
+                    let value = value(&arg, inline_value, &mut args)?;
+                    config.renders.push(parsed(&arg, value, parse_render)?);
+                }
+                _ => return Err(ConfigError::UnknownFlag(arg)),
+            }
+        }
+
+        if config.files.is_empty() {
+            config.files.push("-".into());
+        }
+        if config.mode == Mode::Decode {
+            if let Some((flag, _)) = NEEDS_EXEC.iter().find(|(_, set)| set(&config)) {
+                return Err(ConfigError::NeedsExec(flag.to_string()));
+            }
+        }
+        // how much memory there is depends on --segmented, which can come
+        // after the dumps
+        let memory_size = if config.segmented {
+            SEGMENTED_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        };
+        if let Some(dump) = config
+            .dumps
+            .iter()
+            .find(|dump| dump.start.saturating_add(dump.len) > memory_size)
+        {
+            return Err(ConfigError::OutOfRange {
+                start: dump.start,
+                len: dump.len,
+            });
+        }
+        if config.mode == Mode::Simulate {
+            if let Some((flag, _)) = NOT_WITH_EXEC.iter().find(|(_, set)| set(&config)) {
+                return Err(ConfigError::Conflict(flag.to_string(), "--exec".into()));
+            }
+        }
+        if let Some((a, b, _)) = CONFLICTS.iter().find(|(_, _, both)| both(&config)) {
+            return Err(ConfigError::Conflict(a.to_string(), b.to_string()));
+        }
+        Ok(config)
+    }
+}
+
+// whether a flag, or a pair of them, was given
+type Given = fn(&Config) -> bool;
+
+// flags that only do anything to a running program
+const NEEDS_EXEC: &[(&str, Given)] = &[
+    ("--trace-addresses", |config| config.trace_addresses),
+    ("--trace", |config| config.trace),
+    ("--image", |config| config.image),
+    ("--byte-registers", |config| config.byte_registers),
+    ("--stats", |config| config.stats),
+    ("--segmented", |config| config.segmented),
+    ("--set", |config| !config.registers.is_empty()),
+    ("--stack-floor", |config| config.stack_floor.is_some()),
+    ("--max-instructions", |config| {
+        config.max_instructions.is_some()
+    }),
+    ("--break", |config| !config.breakpoints.is_empty()),
+    ("--break-stop", |config| config.break_stop),
+    ("--step", |config| config.step),
+    ("--dump", |config| !config.dumps.is_empty()),
+    ("--render", |config| !config.renders.is_empty()),
+    ("--verify", |config| config.verify.is_some()),
+];
+
+// flags that don't do anything to a running program
+const NOT_WITH_EXEC: &[(&str, Given)] = &[
+    ("--labels", |config| config.labels),
+    ("--listing", |config| config.listing),
+    ("--output", |config| config.output.is_some()),
+    // the sim has to stop at data anyway
+    ("--skip-unknown", |config| config.skip_unknown),
+    ("--addresses", |config| config.addresses),
+    ("--range", |config| config.range.is_some()),
+    ("--count", |config| config.count),
+];
+
+// pairs of flags that can't go together, and whether both were given
+const CONFLICTS: &[(&str, &str, Given)] = &[
+    // counting streams the input, there's no going back to the start
+    ("--count", "--range", |config| {
+        config.count && config.range.is_some()
+    }),
+    ("--count", "--labels", |config| {
+        config.count && config.labels
+    }),
+    ("--count", "--listing", |config| {
+        config.count && config.listing
+    }),
+    // the listing has them already
+    ("--addresses", "--listing", |config| {
+        config.addresses && config.listing
+    }),
+    ("--addresses", "--labels", |config| {
+        config.addresses && config.labels
+    }),
+    ("--count", "--addresses", |config| {
+        config.addresses && config.count
+    }),
+    ("--labels", "--listing", |config| {
+        config.labels && config.listing
+    }),
+    ("--labels", "--cycle-estimate", |config| {
+        config.labels && (config.cycle_estimate || config.cycle_estimate_8088)
+    }),
+    // stdin is where the commands come from
+    ("--step", "a program on stdin", |config| {
+        config.step && config.files.iter().any(|file| file == "-")
+    }),
+    ("--cycle-estimate", "--cycle-estimate-8088", |config| {
+        config.cycle_estimate && config.cycle_estimate_8088
+    }),
+];
+
+// the flag's value, after the = or in the next argument
+fn value(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, ConfigError> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or_else(|| ConfigError::MissingValue(flag.into()))
+}
+
+// the value, as a number that has to fit in a `T`
+fn number<T: TryFrom<usize>>(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<T, ConfigError> {
+    let value = value(flag, inline_value, args)?;
+    parsed(flag, value, |value| {
+        parse_number(value).and_then(|n| T::try_from(n).ok())
+    })
+}
+
+// the value, or BadValue if `parse` doesn't take it
+fn parsed<T>(
+    flag: &str,
+    value: String,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<T, ConfigError> {
+    parse(&value).ok_or_else(|| ConfigError::BadValue {
+        flag: flag.into(),
+        value,
+    })
+}
+
+// decimal, or hex with a 0x in front
+pub(crate) fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// start:end, with start no later than end
+fn parse_range(s: &str) -> Option<Range<usize>> {
+    let (start, end) = s.split_once(':')?;
+    let (start, end) = (parse_number(start)?, parse_number(end)?);
+    (start <= end).then_some(start..end)
+}
+
+// start:len:file
+fn parse_dump(s: &str) -> Option<Dump> {
+    let mut parts = s.splitn(3, ':');
+    let start = parse_number(parts.next()?)?;
+    let len = parse_number(parts.next()?)?;
+    let file = parts.next().filter(|file| !file.is_empty())?;
+    Some(Dump {
+        start,
+        len,
+        file: file.into(),
+    })
+}
+
+// WxH:start:file. the image can run past the end of memory, that part is
+// just black
+fn parse_render(s: &str) -> Option<Render> {
+    let mut parts = s.splitn(3, ':');
+    let (width, height) = parts.next()?.split_once('x')?;
+    let (width, height) = (parse_number(width)?, parse_number(height)?);
+    // so the byte count of the pixels can't overflow
+    width.checked_mul(height)?.checked_mul(4)?;
+    let start = parse_number(parts.next()?)?;
+    let file = parts.next().filter(|file| !file.is_empty())?;
+    Some(Render {
+        width,
+        height,
+        start,
+        file: file.into(),
+    })
+}
+
+// `reg=value`, where the value has to fit the register
+fn parse_register(s: &str) -> Option<(RegIndex, u16)> {
+    let (name, value) = s.split_once('=')?;
+    let reg = RegIndex::from_mnemonic(name)?;
+    let value = parse_number(value)?;
+    let max = match reg.region {
+        Region::Xtended => u16::MAX as usize,
+        Region::Low | Region::High => u8::MAX as usize,
+    };
+    (value <= max).then_some((reg, value as u16))
+}
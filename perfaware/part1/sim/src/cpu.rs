@@ -0,0 +1,1100 @@
+use crate::instruction::*;
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum Flag {
+    Parity = 0,
+    Zero,
+    Carry,
+    Sign,
+    AuxCarry,
+    Overflow,
+    // which way the string instructions step si and di, down when it's set
+    Direction,
+}
+
+impl Flag {
+    // in the order of their bits in the FLAGS register, which is also the
+    // order the reference output prints them in
+    const ALL: [Self; Self::num()] = [
+        Self::Carry,
+        Self::Parity,
+        Self::AuxCarry,
+        Self::Zero,
+        Self::Sign,
+        Self::Direction,
+        Self::Overflow,
+    ];
+
+    pub(crate) const fn num() -> usize {
+        std::mem::variant_count::<Self>()
+    }
+
+    // where the flag lives in the FLAGS register
+    pub fn bit(&self) -> u16 {
+        match self {
+            Flag::Carry => 1 << 0,
+            Flag::Parity => 1 << 2,
+            Flag::AuxCarry => 1 << 4,
+            Flag::Zero => 1 << 6,
+            Flag::Sign => 1 << 7,
+            Flag::Direction => 1 << 10,
+            Flag::Overflow => 1 << 11,
+        }
+    }
+
+    pub fn format(&self) -> char {
+        match self {
+            Flag::Parity => 'P',
+            Flag::Zero => 'Z',
+            Flag::Carry => 'C',
+            Flag::Sign => 'S',
+            Flag::AuxCarry => 'A',
+            Flag::Overflow => 'O',
+            Flag::Direction => 'D',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        Self::ALL.into_iter().find(|flag| flag.format() == c)
+    }
+}
+
+// indexed by `Flag as usize`
+pub type Flags = [bool; Flag::num()];
+
+// only the set flags, in the order the reference output uses
+pub fn format_flags(flags: &Flags) -> String {
+    Flag::ALL
+        .into_iter()
+        .filter(|&flag| flags[flag as usize])
+        .map(|flag| flag.format())
+        .collect()
+}
+
+// the other way, in any order. None if there's a letter that isn't a flag
+pub fn flags_from_string(s: &str) -> Option<Flags> {
+    let mut flags = [false; Flag::num()];
+    for c in s.chars() {
+        flags[Flag::from_char(c)? as usize] = true;
+    }
+    Some(flags)
+}
+
+// the FLAGS register as pushf would push it. TF and IF aren't simulated,
+// so their bits are always clear
+pub fn flags_to_word(flags: &Flags) -> u16 {
+    Flag::ALL
+        .into_iter()
+        .filter(|&flag| flags[flag as usize])
+        .fold(0, |word, flag| word | flag.bit())
+}
+
+// the bits that aren't flags we simulate are ignored
+pub fn flags_from_word(word: u16) -> Flags {
+    let mut flags = [false; Flag::num()];
+    for flag in Flag::ALL {
+        flags[flag as usize] = word & flag.bit() != 0;
+    }
+    flags
+}
+
+// all that a 16 bit offset can reach
+pub const MEMORY_SIZE: usize = 1 << 16;
+// all that 20 bits of address can reach
+pub const SEGMENTED_MEMORY_SIZE: usize = 1 << 20;
+
+// segment * 16 + offset, wrapping at 1MB like the 8086 does
+pub fn physical_address(segment: u16, offset: u16) -> usize {
+    (((segment as usize) << 4) + offset as usize) % SEGMENTED_MEMORY_SIZE
+}
+
+// what `CPU::snapshot` saves, for going back to later
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    registers: [u16; Reg::num()],
+    flags: Flags,
+    // only in a full snapshot, since copying it is the expensive part
+    memory: Option<Vec<u8>>,
+}
+
+pub struct CPU {
+    // flat by default, where an address is just the offset and the segment
+    // registers are ignored. see `physical_addr` for segmented mode
+    memory: Vec<u8>,
+    segmented: bool,
+    // indexed by `Reg as usize`
+    registers: [u16; Reg::num()],
+    flags: Flags,
+    // filled in by `set_dest` while an instruction executes
+    reg_writes: Vec<RegWrite>,
+    // same, for every trip to memory
+    mem_accesses: Vec<MemAccess>,
+    // the lowest sp can go, see `sp_after`. no checking at all when it's
+    // not set
+    stack_floor: Option<u16>,
+}
+
+// what an instruction did
+pub struct Exec {
+    pub reg_writes: Vec<RegWrite>,
+    pub mem_accesses: Vec<MemAccess>,
+    pub old_flags: Flags,
+    pub new_flags: Flags,
+    // the sim should stop here
+    pub halted: bool,
+}
+
+impl Exec {
+    pub fn flags_changed(&self) -> bool {
+        self.old_flags != self.new_flags
+    }
+}
+
+// the whole register before and after, even if only half was written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegWrite {
+    pub reg: Reg,
+    pub old: u16,
+    pub new: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: usize, // physical
+    pub width: Width,
+    pub is_write: bool,
+}
+
+fn check_parity(n: u16) -> bool {
+    let lsb = n & 0xff;
+    lsb.count_ones().is_multiple_of(2)
+}
+
+// carry out of the low nibble, which shows up as a difference in bit 4
+fn check_aux_carry(src: u16, dst: u16, result: u16) -> bool {
+    (src ^ dst ^ result) & 0x10 != 0
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CPU {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; MEMORY_SIZE],
+            segmented: false,
+            registers: [0; Reg::num()],
+            flags: [false; Flag::num()],
+            reg_writes: vec![],
+            mem_accesses: vec![],
+            stack_floor: None,
+        }
+    }
+
+    // the full megabyte, with every memory access offset by its segment
+    pub fn new_segmented() -> Self {
+        Self {
+            memory: vec![0; SEGMENTED_MEMORY_SIZE],
+            segmented: true,
+            ..Self::new()
+        }
+    }
+
+    // registers set up front, so a test doesn't have to run movs first
+    pub fn with_registers(mut self, registers: &[(RegIndex, u16)]) -> Self {
+        for (reg, val) in registers {
+            self.set_dest(Loc::Reg(*reg), *val);
+        }
+        self.reg_writes.clear();
+        self
+    }
+
+    // back to how `new` left it, but memory is only zeroed when asked, so
+    // a program loaded into it can be run again
+    pub fn reset(&mut self, clear_memory: bool) {
+        self.registers = [0; Reg::num()];
+        self.flags = [false; Flag::num()];
+        self.reg_writes.clear();
+        self.mem_accesses.clear();
+        if clear_memory {
+            self.memory.fill(0);
+        }
+    }
+
+    // registers and flags, which is cheap enough to take every instruction
+    pub fn snapshot(&self) -> CpuState {
+        CpuState {
+            registers: self.registers,
+            flags: self.flags,
+            memory: None,
+        }
+    }
+
+    // memory too
+    pub fn snapshot_full(&self) -> CpuState {
+        CpuState {
+            memory: Some(self.memory.clone()),
+            ..self.snapshot()
+        }
+    }
+
+    // memory is left alone unless the snapshot has it, in which case it has
+    // to come from a CPU with as much memory as this one
+    pub fn restore(&mut self, state: &CpuState) {
+        self.registers = state.registers;
+        self.flags = state.flags;
+        if let Some(memory) = &state.memory {
+            self.memory.copy_from_slice(memory);
+        }
+    }
+
+    pub fn set_stack_floor(&mut self, floor: Option<u16>) {
+        self.stack_floor = floor;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // the program goes in memory like anything else, so it can read and
+    // write its own bytes. `addr` is physical, and it has to fit
+    pub fn load_program(&mut self, addr: usize, program: &[u8]) {
+        self.memory[addr..addr + program.len()].copy_from_slice(program);
+    }
+
+    // peeks and pokes, for setting memory up and checking it from outside
+    // the sim. `addr` is physical, anywhere in the megabyte in segmented
+    // mode and in the first 64K otherwise. the high byte of a word at the
+    // very end wraps around to 0, the way it does at the end of a segment.
+    // none of these count as memory accesses
+    pub fn read_byte(&self, addr: u32) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    pub fn read_word(&self, addr: u32) -> u16 {
+        u16::from_le_bytes([self.read_byte(addr), self.read_byte(self.next_addr(addr))])
+    }
+
+    pub fn write_byte(&mut self, addr: u32, val: u8) {
+        self.memory[addr as usize] = val;
+    }
+
+    pub fn write_word(&mut self, addr: u32, val: u16) {
+        let [lo, hi] = val.to_le_bytes();
+        self.write_byte(addr, lo);
+        self.write_byte(self.next_addr(addr), hi);
+    }
+
+    fn next_addr(&self, addr: u32) -> u32 {
+        (addr + 1) % self.memory.len() as u32
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn ip(&self) -> u16 {
+        self.get_src(Loc::Reg(RegIndex::IP))
+    }
+
+    pub fn set_ip(&mut self, ip: u16) {
+        self.set_dest(Loc::Reg(RegIndex::IP), ip);
+    }
+
+    // `size` is how many bytes `inst` was decoded from. like the real thing,
+    // ip points past the instruction while it runs, which is what jumps are
+    // relative to and what call pushes
+    pub fn exec(&mut self, inst: Instruction, size: usize) -> Result<Exec, ExecError> {
+        self.reg_writes.clear();
+        self.mem_accesses.clear();
+        let old_flags = self.flags;
+        self.set_ip(self.ip().wrapping_add(size as u16));
+        let halted = inst.op == Op::Hlt;
+        self.exec_inst(inst.op)?;
+        Ok(Exec {
+            reg_writes: std::mem::take(&mut self.reg_writes),
+            mem_accesses: std::mem::take(&mut self.mem_accesses),
+            old_flags,
+            new_flags: self.flags,
+            halted,
+        })
+    }
+
+    fn exec_inst(&mut self, op: Op) -> Result<(), ExecError> {
+        match op {
+            Op::Mov(mov) => {
+                let width = Loc::op_width(mov.dst, mov.src);
+                let src = self.fetch(mov.src, width);
+                self.write(mov.dst, src, width);
+            }
+            Op::Jump(jump) => {
+                let should_jump = self.is_jump_taken(&jump);
+                if matches!(
+                    jump.typ,
+                    JumpType::Loop | JumpType::Loopz | JumpType::Loopnz
+                ) {
+                    self.dec_cx();
+                }
+                if should_jump {
+                    self.set_ip(self.ip().wrapping_add(jump.offset as i16 as u16));
+                }
+            }
+            Op::Call(call) => {
+                let ret = self.ip();
+                let sp = self.grow_stack()?;
+                self.store(Reg::SS, sp, ret, Width::Word);
+                self.set_ip(ret.wrapping_add(call.offset as u16));
+            }
+            Op::CallIndirect(call) => {
+                // the target is read before the push, which could overwrite it
+                let (ip, cs) = self.fetch_target(&call);
+                if let Some(cs) = cs {
+                    let sp = self.grow_stack()?;
+                    let old_cs = self.get_src(Loc::Reg(RegIndex::CS));
+                    self.store(Reg::SS, sp, old_cs, Width::Word);
+                    self.set_dest(Loc::Reg(RegIndex::CS), cs);
+                }
+                let sp = self.grow_stack()?;
+                self.store(Reg::SS, sp, self.ip(), Width::Word);
+                self.set_ip(ip);
+            }
+            Op::JmpIndirect(jmp) => {
+                let (ip, cs) = self.fetch_target(&jmp);
+                if let Some(cs) = cs {
+                    self.set_dest(Loc::Reg(RegIndex::CS), cs);
+                }
+                self.set_ip(ip);
+            }
+            Op::Ret(ret) => {
+                let ip = self.pop_word()?;
+                self.set_ip(ip);
+                if let Some(n) = ret.pop {
+                    let sp = self.sp_after(n as i32)?;
+                    self.set_dest(Loc::Reg(RegIndex::SP), sp);
+                }
+            }
+            Op::Add(add) => {
+                let width = Loc::op_width(add.dst, add.src);
+                let (src, dst) = (self.fetch(add.src, width), self.fetch(add.dst, width));
+                let sum = self.add_and_flags(dst, src, false, width);
+                self.write(add.dst, sum, width);
+            }
+            Op::Adc(adc) => {
+                let width = Loc::op_width(adc.dst, adc.src);
+                let (src, dst) = (self.fetch(adc.src, width), self.fetch(adc.dst, width));
+                let cf = self.get_flag(Flag::Carry);
+                let sum = self.add_and_flags(dst, src, cf, width);
+                self.write(adc.dst, sum, width);
+            }
+            Op::Sub(sub) => {
+                let width = Loc::op_width(sub.dst, sub.src);
+                let (src, dst) = (self.fetch(sub.src, width), self.fetch(sub.dst, width));
+                let diff = self.sub_and_flags(dst, src, false, width);
+                self.write(sub.dst, diff, width);
+            }
+            Op::Sbb(sbb) => {
+                let width = Loc::op_width(sbb.dst, sbb.src);
+                let (src, dst) = (self.fetch(sbb.src, width), self.fetch(sbb.dst, width));
+                let cf = self.get_flag(Flag::Carry);
+                let diff = self.sub_and_flags(dst, src, cf, width);
+                self.write(sbb.dst, diff, width);
+            }
+            Op::Cmp(cmp) => {
+                let width = Loc::op_width(cmp.dst, cmp.src);
+                let (src, dst) = (self.fetch(cmp.src, width), self.fetch(cmp.dst, width));
+                self.sub_and_flags(dst, src, false, width);
+            }
+            Op::And(and) => {
+                let width = Loc::op_width(and.dst, and.src);
+                let result = self.fetch(and.dst, width) & self.fetch(and.src, width);
+                self.set_logic_flags(result, width);
+                self.write(and.dst, result, width);
+            }
+            Op::Or(or) => {
+                let width = Loc::op_width(or.dst, or.src);
+                let result = self.fetch(or.dst, width) | self.fetch(or.src, width);
+                self.set_logic_flags(result, width);
+                self.write(or.dst, result, width);
+            }
+            Op::Xor(xor) => {
+                let width = Loc::op_width(xor.dst, xor.src);
+                let result = self.fetch(xor.dst, width) ^ self.fetch(xor.src, width);
+                self.set_logic_flags(result, width);
+                self.write(xor.dst, result, width);
+            }
+            Op::Test(test) => {
+                let width = Loc::op_width(test.dst, test.src);
+                let result = self.fetch(test.dst, width) & self.fetch(test.src, width);
+                self.set_logic_flags(result, width);
+            }
+            Op::Xchg(xchg) => {
+                let width = Loc::op_width(xchg.dst, xchg.src);
+                let (dst_val, src_val) = (self.fetch(xchg.dst, width), self.fetch(xchg.src, width));
+                // memory goes first, so the registers its address depends on
+                // still have their old values
+                if matches!(xchg.src, Loc::EAC(_)) {
+                    self.write(xchg.src, dst_val, width);
+                    self.write(xchg.dst, src_val, width);
+                } else {
+                    self.write(xchg.dst, src_val, width);
+                    self.write(xchg.src, dst_val, width);
+                }
+            }
+            // inc and dec leave CF alone, that's the only difference from
+            // adding or subtracting 1
+            Op::Inc(inc) => {
+                let cf = self.get_flag(Flag::Carry);
+                let val = self.fetch(inc.dst, inc.width);
+                let result = self.add_and_flags(val, 1, false, inc.width);
+                self.set_flag(Flag::Carry, cf);
+                self.write(inc.dst, result, inc.width);
+            }
+            Op::Dec(dec) => {
+                let cf = self.get_flag(Flag::Carry);
+                let val = self.fetch(dec.dst, dec.width);
+                let result = self.sub_and_flags(val, 1, false, dec.width);
+                self.set_flag(Flag::Carry, cf);
+                self.write(dec.dst, result, dec.width);
+            }
+            Op::Push(push) => {
+                // SP is decremented before the source is read, so `push sp`
+                // pushes the new value like the 8086 does
+                let sp = self.grow_stack()?;
+                let val = self.fetch(push.src, Width::Word);
+                self.store(Reg::SS, sp, val, Width::Word);
+            }
+            Op::Pop(pop) => {
+                let val = self.pop_word()?;
+                self.set_dest(pop.dst, val);
+            }
+            Op::Pushf => {
+                let sp = self.grow_stack()?;
+                self.store(Reg::SS, sp, flags_to_word(&self.flags), Width::Word);
+            }
+            Op::Popf => {
+                self.flags = flags_from_word(self.pop_word()?);
+            }
+            // there are no interrupts to wake it up, so this is the end
+            Op::Hlt => {}
+            // there's no coprocessor, so nothing to wait for, and nothing to
+            // take an escape
+            Op::Wait | Op::Esc(_) => {}
+            Op::Cld => self.set_flag(Flag::Direction, false),
+            Op::Std => self.set_flag(Flag::Direction, true),
+            // nothing is on the other end of any port, reads get 0 and
+            // writes go nowhere
+            Op::In(inp) => {
+                let acc = match inp.width {
+                    Width::Byte => RegIndex::AL,
+                    Width::Word => RegIndex::AX,
+                };
+                self.set_dest(Loc::Reg(acc), 0);
+            }
+            Op::Out(_) => {}
+            Op::Unknown(b) => return Err(ExecError::NotCode(b)),
+            Op::Adjust(adjust) => self.adjust(adjust)?,
+            Op::Mul(mul) => {
+                let src = self.fetch(mul.src, mul.width);
+                self.mul(src, mul.width, mul.signed);
+            }
+            Op::Div(div) => {
+                let src = self.fetch(div.src, div.width);
+                self.div(src, div.width, div.signed)?;
+            }
+            Op::Shift(shift) => {
+                let count = if shift.by_cl {
+                    self.get_src(Loc::Reg(RegIndex::CL))
+                } else {
+                    1
+                };
+                let val = self.fetch(shift.dst, shift.width);
+                let result = self.shift(shift.op, val, count, shift.width);
+                self.write(shift.dst, result, shift.width);
+            }
+            Op::Str(s) => self.string(s),
+        }
+        Ok(())
+    }
+
+    // cx is checked before each pass, so with it at 0 nothing happens. repe
+    // and repne also stop on a compare that came out the wrong way
+    fn string(&mut self, s: Str) {
+        let Some(rep) = s.rep else {
+            self.string_step(s);
+            return;
+        };
+        while self.get_src(Loc::Reg(RegIndex::CX)) != 0 {
+            self.string_step(s);
+            self.dec_cx();
+            if s.op.compares() && self.get_flag(Flag::Zero) != (rep == Rep::Rep) {
+                break;
+            }
+        }
+    }
+
+    // the source is ds:si, unless there's a segment override, and the
+    // destination is always es:di. both move on by the width, backwards when
+    // DF is set
+    fn string_step(
+        &mut self,
+        Str {
+            op, width, segment, ..
+        }: Str,
+    ) {
+        let src = Loc::EAC(EAC {
+            segment,
+            ..EAC::new(EABase::Si, None)
+        });
+        let dst = Loc::EAC(EAC {
+            segment: Some(Reg::ES),
+            ..EAC::new(EABase::Di, None)
+        });
+        let acc = Loc::Reg(RegIndex::acc(width == Width::Word));
+        match op {
+            StrOp::Movs => {
+                let val = self.fetch(src, width);
+                self.write(dst, val, width);
+            }
+            StrOp::Cmps => {
+                let a = self.fetch(src, width);
+                let b = self.fetch(dst, width);
+                self.sub_and_flags(a, b, false, width);
+            }
+            StrOp::Stos => {
+                let val = self.get_src(acc);
+                self.write(dst, val, width);
+            }
+            StrOp::Lods => {
+                let val = self.fetch(src, width);
+                self.set_dest(acc, val);
+            }
+            StrOp::Scas => {
+                let a = self.get_src(acc);
+                let b = self.fetch(dst, width);
+                self.sub_and_flags(a, b, false, width);
+            }
+        }
+
+        let step: u16 = match width {
+            Width::Byte => 1,
+            Width::Word => 2,
+        };
+        let step = if self.get_flag(Flag::Direction) {
+            step.wrapping_neg()
+        } else {
+            step
+        };
+        if matches!(op, StrOp::Movs | StrOp::Cmps | StrOp::Lods) {
+            let si = self.get_src(Loc::Reg(RegIndex::SI)).wrapping_add(step);
+            self.set_dest(Loc::Reg(RegIndex::SI), si);
+        }
+        if op != StrOp::Lods {
+            let di = self.get_src(Loc::Reg(RegIndex::DI)).wrapping_add(step);
+            self.set_dest(Loc::Reg(RegIndex::DI), di);
+        }
+    }
+
+    // makes room for a word on the stack, returning the new sp
+    fn grow_stack(&mut self) -> Result<u16, ExecError> {
+        let sp = self.sp_after(-2)?;
+        self.set_dest(Loc::Reg(RegIndex::SP), sp);
+        Ok(sp)
+    }
+
+    fn pop_word(&mut self) -> Result<u16, ExecError> {
+        let sp = self.get_src(Loc::Reg(RegIndex::SP));
+        let new_sp = self.sp_after(2)?;
+        self.record_access(Reg::SS, sp, Width::Word, false);
+        let val = self.load(Reg::SS, sp, Width::Word);
+        self.set_dest(Loc::Reg(RegIndex::SP), new_sp);
+        Ok(val)
+    }
+
+    // where sp ends up after moving it `by` bytes. the 8086 just wraps, but
+    // with a floor set, going below it (or below 0) is an overflow, and going
+    // past the top of the segment is an underflow
+    fn sp_after(&self, by: i32) -> Result<u16, ExecError> {
+        let sp = self.get_src(Loc::Reg(RegIndex::SP)) as i32 + by;
+        if let Some(floor) = self.stack_floor {
+            if sp < floor as i32 {
+                return Err(ExecError::StackOverflow);
+            }
+            if sp > u16::MAX as i32 {
+                return Err(ExecError::StackUnderflow);
+            }
+        }
+        Ok(sp as u16)
+    }
+
+    // shifts one bit at a time so CF always ends up holding the last bit that
+    // fell off. the 8086 doesn't mask the count, so this can run up to 255
+    // times
+    fn shift(&mut self, op: ShiftOp, val: u16, count: u16, width: Width) -> u16 {
+        if count == 0 {
+            return val;
+        }
+
+        let msb = |n: u16| n & width.sign_bit() != 0;
+        let original = val & width.mask();
+        let mut val = original;
+        let mut cf = self.get_flag(Flag::Carry);
+        for _ in 0..count {
+            let (shifted, out) = match op {
+                ShiftOp::Shl => (val << 1, msb(val)),
+                ShiftOp::Shr => (val >> 1, val & 1 != 0),
+                ShiftOp::Sar => ((val >> 1) | (val & width.sign_bit()), val & 1 != 0),
+                ShiftOp::Rol => ((val << 1) | msb(val) as u16, msb(val)),
+                ShiftOp::Ror => (
+                    (val >> 1) | if val & 1 != 0 { width.sign_bit() } else { 0 },
+                    val & 1 != 0,
+                ),
+                ShiftOp::Rcl => ((val << 1) | cf as u16, msb(val)),
+                ShiftOp::Rcr => (
+                    (val >> 1) | if cf { width.sign_bit() } else { 0 },
+                    val & 1 != 0,
+                ),
+            };
+            val = shifted & width.mask();
+            cf = out;
+        }
+        self.set_flag(Flag::Carry, cf);
+
+        // OF is only defined for single bit shifts
+        if count == 1 {
+            let of = match op {
+                ShiftOp::Shl | ShiftOp::Rol | ShiftOp::Rcl => msb(val) != cf,
+                ShiftOp::Shr => msb(original),
+                ShiftOp::Sar => false,
+                ShiftOp::Ror | ShiftOp::Rcr => msb(val) != msb(val << 1),
+            };
+            self.set_flag(Flag::Overflow, of);
+        }
+
+        // rotates only touch CF and OF
+        if matches!(op, ShiftOp::Shl | ShiftOp::Shr | ShiftOp::Sar) {
+            self.set_flag(Flag::Zero, val == 0);
+            self.set_flag(Flag::Sign, msb(val));
+            self.set_flag(Flag::Parity, check_parity(val));
+        }
+        val
+    }
+
+    // AL * src8 -> AX, or AX * src16 -> DX:AX. CF and OF are set when the
+    // upper half is significant, the other flags are undefined and left alone
+    fn mul(&mut self, src: u16, width: Width, signed: bool) {
+        let ax = self.get_src(Loc::Reg(RegIndex::AX));
+        let is_upper_significant = match (width, signed) {
+            (Width::Byte, false) => {
+                let product = (ax & 0xff) * (src & 0xff);
+                self.set_dest(Loc::Reg(RegIndex::AX), product);
+                product > 0xff
+            }
+            (Width::Byte, true) => {
+                let product = (ax as i8 as i16) * (src as i8 as i16);
+                self.set_dest(Loc::Reg(RegIndex::AX), product as u16);
+                product != product as i8 as i16
+            }
+            (Width::Word, false) => {
+                let product = ax as u32 * src as u32;
+                self.set_dest(Loc::Reg(RegIndex::AX), product as u16);
+                self.set_dest(Loc::Reg(RegIndex::DX), (product >> 16) as u16);
+                product > 0xffff
+            }
+            (Width::Word, true) => {
+                let product = (ax as i16 as i32) * (src as i16 as i32);
+                self.set_dest(Loc::Reg(RegIndex::AX), product as u16);
+                self.set_dest(Loc::Reg(RegIndex::DX), (product >> 16) as u16);
+                product != product as i16 as i32
+            }
+        };
+        self.set_flag(Flag::Carry, is_upper_significant);
+        self.set_flag(Flag::Overflow, is_upper_significant);
+    }
+
+    // AX / src8 -> AL rem AH, or DX:AX / src16 -> AX rem DX. the flags are
+    // undefined after a divide, so they're left alone
+    fn div(&mut self, src: u16, width: Width, signed: bool) -> Result<(), ExecError> {
+        let ax = self.get_src(Loc::Reg(RegIndex::AX));
+        let dx = self.get_src(Loc::Reg(RegIndex::DX));
+        if src & width.mask() == 0 {
+            return Err(ExecError::DivideByZero);
+        }
+        match (width, signed) {
+            (Width::Byte, false) => {
+                let (quot, rem) = (ax / (src & 0xff), ax % (src & 0xff));
+                if quot > 0xff {
+                    return Err(ExecError::DivideOverflow);
+                }
+                self.set_dest(Loc::Reg(RegIndex::AX), (rem << 8) | quot);
+            }
+            (Width::Byte, true) => {
+                let (dividend, divisor) = (ax as i16 as i32, src as i8 as i32);
+                let (quot, rem) = (dividend / divisor, dividend % divisor);
+                // the 8086 traps on -128 (and -32768 for a word) too, the
+                // quotient has to fit whichever sign it has
+                if !(-0x7f..=0x7f).contains(&quot) {
+                    return Err(ExecError::DivideOverflow);
+                }
+                let (quot, rem) = (quot as u8 as u16, rem as u8 as u16);
+                self.set_dest(Loc::Reg(RegIndex::AX), (rem << 8) | quot);
+            }
+            (Width::Word, false) => {
+                let dividend = ((dx as u32) << 16) | ax as u32;
+                let (quot, rem) = (dividend / src as u32, dividend % src as u32);
+                if quot > 0xffff {
+                    return Err(ExecError::DivideOverflow);
+                }
+                self.set_dest(Loc::Reg(RegIndex::AX), quot as u16);
+                self.set_dest(Loc::Reg(RegIndex::DX), rem as u16);
+            }
+            (Width::Word, true) => {
+                let dividend = (((dx as u32) << 16) | ax as u32) as i32 as i64;
+                let divisor = src as i16 as i64;
+                let (quot, rem) = (dividend / divisor, dividend % divisor);
+                if !(-0x7fff..=0x7fff).contains(&quot) {
+                    return Err(ExecError::DivideOverflow);
+                }
+                self.set_dest(Loc::Reg(RegIndex::AX), quot as u16);
+                self.set_dest(Loc::Reg(RegIndex::DX), rem as u16);
+            }
+        }
+        Ok(())
+    }
+
+    // the decimal ones fix up AL after adding or subtracting packed BCD, the
+    // ASCII ones unpacked BCD in AL with the carry going to AH. OF is
+    // undefined for all of them, and left alone
+    fn adjust(&mut self, adjust: Adjust) -> Result<(), ExecError> {
+        let ax = self.get_src(Loc::Reg(RegIndex::AX));
+        let (al, ah) = (ax & 0xff, ax >> 8);
+        let cf = self.get_flag(Flag::Carry);
+        let low_nibble_over = al & 0x0f > 9 || self.get_flag(Flag::AuxCarry);
+        let al = match adjust {
+            Adjust::Daa | Adjust::Das => {
+                let add = adjust == Adjust::Daa;
+                let mut fix = 0;
+                if low_nibble_over {
+                    fix += 0x06;
+                }
+                // the original AL decides, not the one after the first fix
+                let high_nibble_over = al > 0x99 || cf;
+                if high_nibble_over {
+                    fix += 0x60;
+                }
+                let fixed = if add {
+                    al.wrapping_add(fix)
+                } else {
+                    al.wrapping_sub(fix)
+                };
+                // das can also borrow out of the first fix alone
+                let borrow = !add && low_nibble_over && al < 0x06;
+                self.set_flag(Flag::AuxCarry, low_nibble_over);
+                self.set_flag(Flag::Carry, high_nibble_over || borrow);
+                self.set_szp(fixed, Width::Byte);
+                fixed & 0xff
+            }
+            Adjust::Aaa | Adjust::Aas => {
+                let (al, ah) = match (low_nibble_over, adjust == Adjust::Aaa) {
+                    (false, _) => (al, ah),
+                    (true, true) => (al.wrapping_add(6), ah.wrapping_add(1)),
+                    (true, false) => (al.wrapping_sub(6), ah.wrapping_sub(1)),
+                };
+                self.set_flag(Flag::AuxCarry, low_nibble_over);
+                self.set_flag(Flag::Carry, low_nibble_over);
+                self.set_dest(Loc::Reg(RegIndex::AH), ah & 0xff);
+                al & 0x0f
+            }
+            Adjust::Aam(base) => {
+                if base == 0 {
+                    return Err(ExecError::DivideByZero);
+                }
+                let base = base as u16;
+                self.set_dest(Loc::Reg(RegIndex::AH), al / base);
+                self.set_szp(al % base, Width::Byte);
+                al % base
+            }
+            Adjust::Aad(base) => {
+                let al = (al + ah * base as u16) & 0xff;
+                self.set_dest(Loc::Reg(RegIndex::AH), 0);
+                self.set_szp(al, Width::Byte);
+                al
+            }
+        };
+        self.set_dest(Loc::Reg(RegIndex::AL), al);
+        Ok(())
+    }
+
+    // computes `a + b`, plus 1 with `carry` for adc, at the given width,
+    // setting the flags the way add does
+    fn add_and_flags(&mut self, a: u16, b: u16, carry: bool, width: Width) -> u16 {
+        let (a, b) = (a & width.mask(), b & width.mask());
+        // wide enough that the carry out can't get lost, even with one in
+        let full = a as u32 + b as u32 + carry as u32;
+        let sum = full as u16 & width.mask();
+        self.set_flag(Flag::Carry, full > width.mask() as u32);
+        self.set_flag(Flag::Zero, sum == 0);
+        self.set_flag(Flag::Parity, check_parity(sum));
+        self.set_flag(Flag::Sign, sum & width.sign_bit() != 0);
+        self.set_flag(Flag::AuxCarry, check_aux_carry(b, a, sum));
+        // signed overflow: both operands share a sign that the sum doesn't
+        self.set_flag(
+            Flag::Overflow,
+            (a ^ sum) & (b ^ sum) & width.sign_bit() != 0,
+        );
+        sum
+    }
+
+    // computes `a - b`, minus 1 with `borrow` for sbb, at the given width,
+    // setting the flags the way sub and cmp both do. the caller decides
+    // whether to store the result
+    fn sub_and_flags(&mut self, a: u16, b: u16, borrow: bool, width: Width) -> u16 {
+        let (a, b) = (a & width.mask(), b & width.mask());
+        let diff = a.wrapping_sub(b).wrapping_sub(borrow as u16) & width.mask();
+        self.set_flag(Flag::Carry, b as u32 + borrow as u32 > a as u32); // borrow
+        self.set_flag(Flag::Zero, diff == 0);
+        self.set_flag(Flag::Parity, check_parity(diff));
+        self.set_flag(Flag::Sign, diff & width.sign_bit() != 0);
+        self.set_flag(Flag::AuxCarry, check_aux_carry(b, a, diff));
+        self.set_flag(Flag::Overflow, (a ^ b) & (a ^ diff) & width.sign_bit() != 0);
+        diff
+    }
+
+    // the logical ops always clear CF and OF. AF is undefined on the 8086, so
+    // it keeps whatever it had
+    fn set_logic_flags(&mut self, result: u16, width: Width) {
+        let result = result & width.mask();
+        self.set_flag(Flag::Carry, false);
+        self.set_flag(Flag::Overflow, false);
+        self.set_szp(result, width);
+    }
+
+    fn set_szp(&mut self, result: u16, width: Width) {
+        let result = result & width.mask();
+        self.set_flag(Flag::Zero, result == 0);
+        self.set_flag(Flag::Parity, check_parity(result));
+        self.set_flag(Flag::Sign, result & width.sign_bit() != 0);
+    }
+
+    // whether `jump` would be taken if it ran right now
+    pub fn is_jump_taken(&self, jump: &Jump) -> bool {
+        let zf = self.get_flag(Flag::Zero);
+        let cf = self.get_flag(Flag::Carry);
+        let sf = self.get_flag(Flag::Sign);
+        let of = self.get_flag(Flag::Overflow);
+        let pf = self.get_flag(Flag::Parity);
+        let cx = self.get_src(Loc::Reg(RegIndex::CX));
+        match jump.typ {
+            JumpType::Je => zf,
+            JumpType::Jnz => !zf,
+            JumpType::Jl => sf != of,
+            JumpType::Jnl => sf == of,
+            JumpType::Jle => zf || sf != of,
+            JumpType::Jg => !zf && sf == of,
+            JumpType::Jb => cf,
+            JumpType::Jnb => !cf,
+            JumpType::Jbe => cf || zf,
+            JumpType::Ja => !cf && !zf,
+            JumpType::Jp => pf,
+            JumpType::Jnp => !pf,
+            JumpType::Jo => of,
+            JumpType::Jno => !of,
+            JumpType::Js => sf,
+            JumpType::Jns => !sf,
+            // the loops decrement CX before testing it
+            JumpType::Loop => cx != 1,
+            JumpType::Loopz => cx != 1 && zf,
+            JumpType::Loopnz => cx != 1 && !zf,
+            JumpType::Jcxz => cx == 0,
+        }
+    }
+
+    // unlike `dec cx`, this leaves the flags alone, which is what the LOOP
+    // family needs
+    fn dec_cx(&mut self) {
+        let cx = self.get_src(Loc::Reg(RegIndex::CX)).wrapping_sub(1);
+        self.set_dest(Loc::Reg(RegIndex::CX), cx);
+    }
+
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        self.flags[flag as usize]
+    }
+
+    fn set_flag(&mut self, flag: Flag, val: bool) {
+        self.flags[flag as usize] = val;
+    }
+
+    // memory doesn't know its own width, so this is for operands that do
+    // (registers and immediates) or that are always a word (the stack)
+    pub fn get_src(&self, loc: Loc) -> u16 {
+        self.read(loc, loc.width().unwrap_or(Width::Word))
+    }
+
+    pub fn set_dest(&mut self, loc: Loc, val: u16) {
+        self.write(loc, val, loc.width().unwrap_or(Width::Word));
+    }
+
+    // `width` only matters for memory, a register or immediate has its own
+    pub fn read(&self, loc: Loc, width: Width) -> u16 {
+        match loc {
+            Loc::Imm8(n) => n as _,
+            Loc::Imm16(n) => n as _,
+            Loc::Reg(reg) => {
+                let val = self.registers[reg.register as usize];
+                match reg.region {
+                    Region::Xtended => val,
+                    Region::Low => val & 0x00ff,
+                    Region::High => val >> 8,
+                }
+            }
+            Loc::EAC(eac) => self.load(Self::segment(eac), self.effective_addr(eac), width),
+        }
+    }
+
+    pub fn write(&mut self, loc: Loc, val: u16, width: Width) {
+        match loc {
+            Loc::Reg(reg) => {
+                let old = self.registers[reg.register as usize];
+                let new = match reg.region {
+                    Region::Xtended => val,
+                    Region::Low => (old & 0xff00) | (val & 0x00ff),
+                    Region::High => (old & 0x00ff) | (val << 8),
+                };
+                self.registers[reg.register as usize] = new;
+                self.record_write(reg.register, old, new);
+            }
+            Loc::EAC(eac) => {
+                let offset = self.effective_addr(eac);
+                self.store(Self::segment(eac), offset, val, width);
+            }
+            Loc::Imm8(_) | Loc::Imm16(_) => unreachable!(),
+        }
+    }
+
+    // `read`, but noting down any memory access for `Exec`
+    fn fetch(&mut self, loc: Loc, width: Width) -> u16 {
+        if let Loc::EAC(eac) = loc {
+            let segment = Self::segment(eac);
+            self.record_access(segment, self.effective_addr(eac), width, false);
+        }
+        self.read(loc, width)
+    }
+
+    // the new ip, and for a far pointer the new cs, which is the word after
+    // it in memory
+    fn fetch_target(&mut self, indirect: &Indirect) -> (u16, Option<u16>) {
+        let ip = self.fetch(indirect.target, Width::Word);
+        let (Loc::EAC(eac), true) = (indirect.target, indirect.far) else {
+            return (ip, None);
+        };
+        let segment = Self::segment(eac);
+        let offset = self.effective_addr(eac).wrapping_add(2);
+        self.record_access(segment, offset, Width::Word, false);
+        (ip, Some(self.load(segment, offset, Width::Word)))
+    }
+
+    fn record_access(&mut self, segment: Reg, offset: u16, width: Width, is_write: bool) {
+        self.mem_accesses.push(MemAccess {
+            addr: self.physical_addr(segment, offset),
+            width,
+            is_write,
+        });
+    }
+
+    // a register written twice (xchg al, ah) shows up once, with its
+    // original value
+    fn record_write(&mut self, reg: Reg, old: u16, new: u16) {
+        match self.reg_writes.iter_mut().find(|w| w.reg == reg) {
+            Some(write) => write.new = new,
+            None => self.reg_writes.push(RegWrite { reg, old, new }),
+        }
+    }
+
+    // the displacement wraps around within the 64k offset space
+    pub fn effective_addr(&self, eac: EAC) -> u16 {
+        let displacement = eac.displacement.unwrap_or(0) as u16;
+        self.get_offset(eac.base).wrapping_add(displacement)
+    }
+
+    // an override prefix wins over the usual one
+    fn segment(eac: EAC) -> Reg {
+        eac.segment.unwrap_or(eac.default_segment())
+    }
+
+    // flat mode acts like every segment register is 0
+    pub fn physical_addr(&self, segment: Reg, offset: u16) -> usize {
+        let segment = if self.segmented {
+            self.registers[segment as usize]
+        } else {
+            0
+        };
+        physical_address(segment, offset)
+    }
+
+    // a word's high byte wraps around to the start of the segment, not into
+    // the next one
+    fn load(&self, segment: Reg, offset: u16, width: Width) -> u16 {
+        let lo = self.memory[self.physical_addr(segment, offset)];
+        match width {
+            Width::Byte => lo as u16,
+            Width::Word => {
+                let hi = self.memory[self.physical_addr(segment, offset.wrapping_add(1))];
+                u16::from_le_bytes([lo, hi])
+            }
+        }
+    }
+
+    fn store(&mut self, segment: Reg, offset: u16, val: u16, width: Width) {
+        self.record_access(segment, offset, width, true);
+        let [lo, hi] = val.to_le_bytes();
+        let addr = self.physical_addr(segment, offset);
+        self.memory[addr] = lo;
+        if width == Width::Word {
+            let addr = self.physical_addr(segment, offset.wrapping_add(1));
+            self.memory[addr] = hi;
+        }
+    }
+
+    fn get_offset(&self, base: EABase) -> u16 {
+        let reg = |reg| self.get_src(Loc::Reg(reg));
+        match base {
+            EABase::DirectAddr(n) => n,
+            EABase::BxSi => reg(RegIndex::BX).wrapping_add(reg(RegIndex::SI)),
+            EABase::BxDi => reg(RegIndex::BX).wrapping_add(reg(RegIndex::DI)),
+            EABase::BpSi => reg(RegIndex::BP).wrapping_add(reg(RegIndex::SI)),
+            EABase::BpDi => reg(RegIndex::BP).wrapping_add(reg(RegIndex::DI)),
+            EABase::Si => reg(RegIndex::SI),
+            EABase::Di => reg(RegIndex::DI),
+            EABase::Bx => reg(RegIndex::BX),
+            EABase::Bp => reg(RegIndex::BP),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecError {
+    // the real 8086 raises interrupt 0 for these, but we have no interrupt
+    // table to dispatch through, so the simulation stops instead
+    DivideByZero,
+    DivideOverflow,
+    // only with a stack floor set
+    StackOverflow,
+    StackUnderflow,
+    // ran into a byte the decoder kept as data
+    NotCode(u8),
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::DivideByZero => write!(f, "divide by zero"),
+            Self::DivideOverflow => write!(f, "quotient too large for the destination"),
+            Self::StackOverflow => write!(f, "stack overflow, sp went below the floor"),
+            Self::StackUnderflow => write!(f, "stack underflow, sp went past the top"),
+            Self::NotCode(b) => write!(f, "{:#04x} isn't an instruction", b),
+        }
+    }
+}
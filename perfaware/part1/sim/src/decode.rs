@@ -0,0 +1,1263 @@
+// using https://edge.edx.org/c4x/BITSPilani/EEE231/asset/8086_family_Users_Manual_1_.pdf
+// as reference for how to decode the instructions
+use std::ops::Range;
+
+use crate::instruction::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode(u8),
+    // the input ended partway through an instruction
+    UnexpectedEof,
+    // the opcode is known, but the mod-reg-r/m byte picks an operation within
+    // its group that we don't decode
+    UnsupportedModRm {
+        opcode: u8,
+        mod_rm: u8,
+    },
+    // a segment prefix on an instruction with no memory operand, rep on
+    // anything but a string op, or a second one of either, which the decoded
+    // form has nowhere to keep
+    StrayPrefix(u8),
+    // reading the input for `ReadDecoder` failed
+    Io(std::io::ErrorKind),
+    // one of the others, with where it happened and the bytes around it
+    // (the bad one in brackets), to find it by in a big file
+    At {
+        offset: usize,
+        context: String,
+        err: Box<DecodeError>,
+    },
+}
+
+// how many bytes either side of an error to show
+const CONTEXT_LEN: usize = 4;
+
+impl DecodeError {
+    // `offset` is where in `bytes` the instruction that failed starts
+    pub fn at(self, bytes: &[u8], offset: usize) -> Self {
+        self.at_in(bytes, offset, 0)
+    }
+
+    // same, for `bytes` that start `base` bytes into the input
+    fn at_in(self, bytes: &[u8], offset: usize, base: usize) -> Self {
+        let start = offset.saturating_sub(CONTEXT_LEN);
+        let end = (offset + CONTEXT_LEN + 1).min(bytes.len());
+        let context = (start..end)
+            .map(|i| {
+                if i == offset {
+                    format!("[{:02X}]", bytes[i])
+                } else {
+                    format!("{:02X}", bytes[i])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self::At {
+            offset: base + offset,
+            context,
+            err: Box::new(self),
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownOpcode(b) => write!(f, "unknown opcode 0b{:08b}", b),
+            Self::UnexpectedEof => write!(f, "input ended in the middle of an instruction"),
+            Self::UnsupportedModRm { opcode, mod_rm } => write!(
+                f,
+                "unsupported mod-reg-r/m byte 0b{:08b} for opcode 0b{:08b}",
+                mod_rm, opcode
+            ),
+            Self::StrayPrefix(b) => write!(f, "prefix 0b{:08b} with nothing it can apply to", b),
+            Self::Io(kind) => write!(f, "can't read the input: {}", kind),
+            Self::At {
+                offset,
+                context,
+                err,
+            } => write!(f, "{} at offset {:#x} (context: {})", err, offset, context),
+        }
+    }
+}
+
+// where the parsers are up to in the input. they look at the first byte of
+// an instruction before taking it, to pick which one parses it
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8], pos: usize) -> Self {
+        Self { bytes, pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    // the offset of the next byte in the whole input
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+}
+
+fn try_parse_jump(b: u8, bs: &mut Cursor) -> Result<Option<Jump>, DecodeError> {
+    let Some(typ) = JumpType::find(b) else {
+        return Ok(None);
+    };
+    next_byte(bs)?; // advance the iterator forward 1 to consume the
+                    // first byte
+    Ok(Some(Jump {
+        typ,
+        offset: consume_i8(bs)?,
+    }))
+}
+
+fn try_parse_call_ret(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1    byte 2
+    // 11101000 IP-INC-LO IP-INC-HI
+    if b == 0b_1110_1000 {
+        next_byte(bs)?;
+        return Ok(Some(Op::Call(Call {
+            offset: consume_i16(bs)?,
+        })));
+    }
+
+    // byte 0
+    // 11000011
+    if b == 0b_1100_0011 {
+        next_byte(bs)?;
+        return Ok(Some(Op::Ret(Ret { pop: None })));
+    }
+
+    // byte 0   byte 1  byte 2
+    // 11000010 DATA-LO DATA-HI
+    if b == 0b_1100_0010 {
+        next_byte(bs)?;
+        return Ok(Some(Op::Ret(Ret {
+            pop: Some(consume_u16(bs)?),
+        })));
+    }
+
+    Ok(None)
+}
+
+fn try_parse_in_out(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 1110|V|1|D|W DATA-8
+    //      1   1 1
+    // V is 0 for a fixed port in byte 1 and 1 for the port in DX, D is 0 for
+    // in and 1 for out
+    if b & 0b_1111_0100 != 0b_1110_0100 {
+        return Ok(None);
+    }
+    next_byte(bs)?;
+    let width = if b & 0b_0000_0001 == 0 {
+        Width::Byte
+    } else {
+        Width::Word
+    };
+    let port = if b & 0b_0000_1000 == 0 {
+        Port::Fixed(next_byte(bs)?)
+    } else {
+        Port::Dx
+    };
+    Ok(Some(if b & 0b_0000_0010 == 0 {
+        Op::In(In { port, width })
+    } else {
+        Op::Out(Out { port, width })
+    }))
+}
+
+fn try_parse_adjust(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    let adjust = match b {
+        0b_0010_0111 => Adjust::Daa,
+        0b_0010_1111 => Adjust::Das,
+        0b_0011_0111 => Adjust::Aaa,
+        0b_0011_1111 => Adjust::Aas,
+        // byte 0   byte 1
+        // 1101010A BASE
+        // A is 0 for aam and 1 for aad
+        0b_1101_0100 | 0b_1101_0101 => {
+            next_byte(bs)?;
+            let base = next_byte(bs)?;
+            return Ok(Some(Op::Adjust(if b & 1 == 0 {
+                Adjust::Aam(base)
+            } else {
+                Adjust::Aad(base)
+            })));
+        }
+        _ => return Ok(None),
+    };
+    next_byte(bs)?;
+    Ok(Some(Op::Adjust(adjust)))
+}
+
+fn try_parse_esc(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 11011XXX MOD|YYY|R/M
+    //           2   3   3
+    // XXXYYY is the coprocessor's opcode
+    if b >> 3 != 0b_1101_1 {
+        return Ok(None);
+    }
+
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let r_m_bits = b1 & 0b_0000_0111;
+    Ok(Some(Op::Esc(Esc {
+        opcode: (b0 & 0b_0000_0111) << 3 | (b1 & 0b_0011_1000) >> 3,
+        src: parse_r_m_loc(bs, mod_bits, r_m_bits, true)?,
+    })))
+}
+
+fn try_parse_str(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0
+    // 1010|OP|W
+    //      3
+    let op = match (b >> 1) & 0b111 {
+        0b010 => StrOp::Movs,
+        0b011 => StrOp::Cmps,
+        0b101 => StrOp::Stos,
+        0b110 => StrOp::Lods,
+        0b111 => StrOp::Scas,
+        _ => return Ok(None),
+    };
+    next_byte(bs)?;
+    let width = if b & 1 == 0 { Width::Byte } else { Width::Word };
+    Ok(Some(Op::Str(Str {
+        op,
+        width,
+        rep: None,
+        segment: None,
+    })))
+}
+
+fn try_parse_push_pop(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0
+    // 0101|P|REG
+    //      1  3
+    // P is 0 for push and 1 for pop
+    if b >> 4 == 0b_0101 {
+        next_byte(bs)?;
+        let loc = Loc::Reg(parse_reg_field(b & 0b_0000_0111, true));
+        return Ok(Some(if b & 0b_0000_1000 == 0 {
+            Op::Push(Push { src: loc })
+        } else {
+            Op::Pop(Pop { dst: loc })
+        }));
+    }
+
+    // byte 0   byte 1
+    // 10001111 MOD|000|R/M
+    //           2   3   3
+    if b == 0b_1000_1111 {
+        next_byte(bs)?;
+        let b1 = next_byte(bs)?;
+        let mod_bits = (b1 & 0b_1100_0000) >> 6;
+        let r_m_bits = b1 & 0b_0000_0111;
+        let dst = parse_r_m_loc(bs, mod_bits, r_m_bits, true)?;
+        return Ok(Some(Op::Pop(Pop { dst })));
+    }
+
+    // byte 0
+    // 1001110|P
+    if b >> 1 == 0b_1001_110 {
+        next_byte(bs)?;
+        return Ok(Some(if b & 0b_0000_0001 == 0 {
+            Op::Pushf
+        } else {
+            Op::Popf
+        }));
+    }
+
+    Ok(None)
+}
+
+fn try_parse_group_f6(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 1111011W MOD|OP|R/M
+    //           2  3  3
+    if b >> 1 != 0b_111_1011 {
+        return Ok(None);
+    }
+
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let width = if w { Width::Word } else { Width::Byte };
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let op_bits = (b1 & 0b_0011_1000) >> 3;
+    let r_m_bits = b1 & 0b_0000_0111;
+    let src = parse_r_m_loc(bs, mod_bits, r_m_bits, w)?;
+    let signed = op_bits & 0b001 != 0;
+    Ok(Some(match op_bits {
+        // the immediate comes after any displacement
+        0b000 => Op::Test(Test {
+            src: consume_imm(bs, w)?,
+            dst: src,
+        }),
+        0b100 | 0b101 => Op::Mul(Mul { src, width, signed }),
+        0b110 | 0b111 => Op::Div(Div { src, width, signed }),
+        _ => {
+            return Err(DecodeError::UnsupportedModRm {
+                opcode: b0,
+                mod_rm: b1,
+            })
+        }
+    }))
+}
+
+fn try_parse_shift(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 110100VW MOD|OP|R/M
+    //           2  3  3
+    // V is 0 to shift by 1 and 1 to shift by CL
+    if b >> 2 != 0b_1101_00 {
+        return Ok(None);
+    }
+
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let by_cl = b0 & 0b_0000_0010 != 0;
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let op_bits = (b1 & 0b_0011_1000) >> 3;
+    let r_m_bits = b1 & 0b_0000_0111;
+    let op = ShiftOp::find(op_bits).ok_or(DecodeError::UnsupportedModRm {
+        opcode: b0,
+        mod_rm: b1,
+    })?;
+    Ok(Some(Op::Shift(Shift {
+        op,
+        dst: parse_r_m_loc(bs, mod_bits, r_m_bits, w)?,
+        width: if w { Width::Word } else { Width::Byte },
+        by_cl,
+    })))
+}
+
+fn try_parse_test(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 1000010W MOD|REG|R/M
+    //           2   3   3
+    if b >> 1 == 0b_1000_010 {
+        let b0 = next_byte(bs)?;
+        let b1 = next_byte(bs)?;
+        let w = b0 & 0b_0000_0001 != 0; // is_wide
+        let mod_bits = (b1 & 0b_1100_0000) >> 6;
+        let reg_bits = (b1 & 0b_0011_1000) >> 3;
+        let r_m_bits = b1 & 0b_0000_0111;
+        let src = Loc::Reg(parse_reg_field(reg_bits, w));
+        let dst = parse_r_m_loc(bs, mod_bits, r_m_bits, w)?;
+        return Ok(Some(Op::Test(Test { src, dst })));
+    }
+
+    // byte 0
+    // 1010100W
+    if b >> 1 == 0b_1010_100 {
+        let b0 = next_byte(bs)?;
+        let w = b0 & 0b_0000_0001 != 0; // is_wide
+        return Ok(Some(Op::Test(Test {
+            src: consume_imm(bs, w)?,
+            dst: Loc::Reg(RegIndex::acc(w)),
+        })));
+    }
+
+    Ok(None)
+}
+
+fn try_parse_xchg(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 1000011W MOD|REG|R/M
+    //           2   3   3
+    if b >> 1 == 0b_1000_011 {
+        let b0 = next_byte(bs)?;
+        let b1 = next_byte(bs)?;
+        let w = b0 & 0b_0000_0001 != 0; // is_wide
+        let mod_bits = (b1 & 0b_1100_0000) >> 6;
+        let reg_bits = (b1 & 0b_0011_1000) >> 3;
+        let r_m_bits = b1 & 0b_0000_0111;
+        let dst = Loc::Reg(parse_reg_field(reg_bits, w));
+        let src = parse_r_m_loc(bs, mod_bits, r_m_bits, w)?;
+        return Ok(Some(Op::Xchg(Xchg { src, dst })));
+    }
+
+    // byte 0
+    // 10010|REG
+    //       3
+    // always exchanges with AX. `xchg ax, ax` is the encoding for nop
+    if b >> 3 == 0b_1001_0 {
+        next_byte(bs)?;
+        return Ok(Some(Op::Xchg(Xchg {
+            src: Loc::Reg(parse_reg_field(b & 0b_0000_0111, true)),
+            dst: Loc::Reg(RegIndex::AX),
+        })));
+    }
+
+    Ok(None)
+}
+
+fn try_parse_inc_dec(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0
+    // 0100|D|REG
+    //      1  3
+    // D is 0 for inc and 1 for dec
+    if b >> 4 == 0b_0100 {
+        next_byte(bs)?;
+        let dst = Loc::Reg(parse_reg_field(b & 0b_0000_0111, true));
+        let width = Width::Word;
+        return Ok(Some(if b & 0b_0000_1000 == 0 {
+            Op::Inc(Inc { dst, width })
+        } else {
+            Op::Dec(Dec { dst, width })
+        }));
+    }
+
+    Ok(None)
+}
+
+// inc, dec, call, jmp and push all share these two opcodes, with the op
+// field saying which
+fn try_parse_group_ff(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // 1111111W MOD|OP|R/M
+    //           2  3  3
+    if b >> 1 != 0b_1111_111 {
+        return Ok(None);
+    }
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let width = if w { Width::Word } else { Width::Byte };
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let op_bits = (b1 & 0b_0011_1000) >> 3;
+    let r_m_bits = b1 & 0b_0000_0111;
+    let unsupported = DecodeError::UnsupportedModRm {
+        opcode: b0,
+        mod_rm: b1,
+    };
+    // everything but inc and dec is word only, and a far pointer has to
+    // be in memory
+    let is_far = op_bits == 0b011 || op_bits == 0b101;
+    if (op_bits > 0b001 && !w) || (is_far && mod_bits == 0b11) {
+        return Err(unsupported);
+    }
+    let loc = parse_r_m_loc(bs, mod_bits, r_m_bits, w)?;
+    let indirect = Indirect {
+        target: loc,
+        far: is_far,
+    };
+    Ok(Some(match op_bits {
+        0b000 => Op::Inc(Inc { dst: loc, width }),
+        0b001 => Op::Dec(Dec { dst: loc, width }),
+        0b010 | 0b011 => Op::CallIndirect(indirect),
+        0b100 | 0b101 => Op::JmpIndirect(indirect),
+        0b110 => Op::Push(Push { src: loc }),
+        _ => return Err(unsupported),
+    }))
+}
+
+// this also works for the R/M field, if MOD = 0b11
+// (register to register copy)
+fn parse_reg_field(reg: u8, w: bool) -> RegIndex {
+    match (reg, w) {
+        (0b000, _) => RegIndex::acc(w),
+
+        (0b001, false) => RegIndex::CL,
+        (0b001, true) => RegIndex::CX,
+
+        (0b010, false) => RegIndex::DL,
+        (0b010, true) => RegIndex::DX,
+
+        (0b011, false) => RegIndex::BL,
+        (0b011, true) => RegIndex::BX,
+
+        (0b100, false) => RegIndex::AH,
+        (0b100, true) => RegIndex::SP,
+
+        (0b101, false) => RegIndex::CH,
+        (0b101, true) => RegIndex::BP,
+
+        (0b110, false) => RegIndex::DH,
+        (0b110, true) => RegIndex::SI,
+
+        (0b111, false) => RegIndex::BH,
+        (0b111, true) => RegIndex::DI,
+
+        _ => panic!("unexpected reg pattern"),
+    }
+}
+
+fn parse_r_m_field(r_m_bits: u8, displacement: Option<i16>) -> EAC {
+    use EABase::*;
+    match r_m_bits {
+        0b000 => EAC::new(BxSi, displacement),
+        0b001 => EAC::new(BxDi, displacement),
+        0b010 => EAC::new(BpSi, displacement),
+        0b011 => EAC::new(BpDi, displacement),
+        0b100 => EAC::new(Si, displacement),
+        0b101 => EAC::new(Di, displacement),
+        0b110 if displacement.is_none() => unreachable!("not handling Direct Address from this function, should have used parse_r_m_direct_addr"),
+        0b110 if displacement.is_some() => EAC::new(Bp, displacement),
+        0b111 => EAC::new(Bx, displacement),
+        _ => panic!("unexpected bit pattern: 0b_{:b}", r_m_bits),
+    }
+}
+
+fn parse_r_m_direct_addr(direct_addr: u16) -> EAC {
+    use EABase::*;
+    EAC::new(DirectAddr(direct_addr), None)
+}
+
+fn parse_mem_to_acc_mov(bs: &mut Cursor) -> Result<Mov, DecodeError> {
+    let b0 = next_byte(bs)?;
+    let addr = consume_u16(bs)?;
+    // byte 0
+    // 1010000W
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let dst = Loc::Reg(RegIndex::acc(w));
+    let src = Loc::EAC(EAC::new(EABase::DirectAddr(addr), None));
+    Ok(Mov { src, dst })
+}
+
+fn parse_acc_to_mem_mov(bs: &mut Cursor) -> Result<Mov, DecodeError> {
+    let b0 = next_byte(bs)?;
+    let addr = consume_u16(bs)?;
+    // byte 0
+    // 1010001W
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let src = Loc::Reg(RegIndex::acc(w));
+    let dst = Loc::EAC(EAC::new(EABase::DirectAddr(addr), None));
+    Ok(Mov { src, dst })
+}
+
+fn parse_r_m_to_r_m(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0   byte 1
+    // OPCODE|DW MOD|REG|R/M
+    //   6       2   3   3
+    let opcode = b >> 2;
+    let is_mov = opcode == 0b_1000_10;
+
+    // inside the 6 bits of OPCODE, if not a mov
+    // 00|BINOP|0
+    //      3
+    let binop = (0b_11_000_1 & opcode == 0)
+        .then(|| BinOpCode::find((opcode >> 1) & 0b111))
+        .flatten();
+    if !is_mov && binop.is_none() {
+        return Ok(None);
+    }
+
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let reg_bits = (b1 & 0b_0011_1000) >> 3;
+    let r_m_bits = b1 & 0b_0000_0111;
+
+    let d_bit = b0 & 0b00000010 != 0;
+    let reg_register = parse_reg_field(reg_bits, w);
+    let r_m_loc = parse_r_m_loc(bs, mod_bits, r_m_bits, w)?;
+    let (src, dst) = if d_bit {
+        (r_m_loc, Loc::Reg(reg_register))
+    } else {
+        (Loc::Reg(reg_register), r_m_loc)
+    };
+    let params = BinopParams::from(is_mov, binop);
+    Ok(Some(binop_to_op(params, src, dst)))
+}
+
+fn parse_imm_to_reg_mov(bs: &mut Cursor) -> Result<Mov, DecodeError> {
+    let b0 = next_byte(bs)?;
+    // byte 0
+    // 1011|W|REG
+    //      1  3
+    let w = (b0 & 0b_0000_1000) != 0;
+    let reg = b0 & 0b_0000_0111;
+    let dst = parse_reg_field(reg, w);
+    let src = if w {
+        Loc::Imm16(consume_u16(bs)?)
+    } else {
+        Loc::Imm8(next_byte(bs)?)
+    };
+    Ok(Mov {
+        src,
+        dst: Loc::Reg(dst),
+    })
+}
+
+fn parse_imm_to_acc(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    // byte 0
+    // 00BIN10W
+    if b & 0b11_000_110 != 0b00_000_100 {
+        // 00_xxx_10x
+        return Ok(None);
+    }
+
+    let Some(binop) = BinOpCode::find((b >> 3) & 0b111) else {
+        return Ok(None);
+    };
+
+    let b0 = next_byte(bs)?;
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+    let (src, dst) = if w {
+        (Loc::Imm16(consume_u16(bs)?), Loc::Reg(RegIndex::acc(w)))
+    } else {
+        (Loc::Imm8(next_byte(bs)?), Loc::Reg(RegIndex::acc(w)))
+    };
+    Ok(Some(binop_to_op(BinopParams::Op(binop), src, dst)))
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug)]
+enum BinOpCode {
+    Add = 0b000,
+    Or = 0b001,
+    Adc = 0b010,
+    Sbb = 0b011,
+    And = 0b100,
+    Sub = 0b101,
+    Xor = 0b110,
+    Cmp = 0b111,
+}
+
+impl BinOpCode {
+    const ALL: [Self; 8] = [
+        Self::Add,
+        Self::Or,
+        Self::Adc,
+        Self::Sbb,
+        Self::And,
+        Self::Sub,
+        Self::Xor,
+        Self::Cmp,
+    ];
+
+    fn find(binop: u8) -> Option<Self> {
+        Self::ALL.iter().find(|b| **b as u8 == binop).copied()
+    }
+}
+
+const MOV_OPCODE: u8 = 0b_110_0011;
+const MOV_OPCODE_LEN: u8 = 7;
+
+const IMM_TO_R_M_OPCODE: u8 = 0b_10_0000;
+const IMM_TO_R_M_OPCODE_LEN: u8 = 6;
+
+fn parse_r_m_loc(bs: &mut Cursor, mod_bits: u8, r_m_bits: u8, w: bool) -> Result<Loc, DecodeError> {
+    Ok(match mod_bits {
+        0b11 => Loc::Reg(parse_reg_field(r_m_bits, w)),
+        0b00 if r_m_bits == 0b110 => Loc::EAC(parse_r_m_direct_addr(consume_u16(bs)?)),
+        0b00 => Loc::EAC(parse_r_m_field(r_m_bits, None)),
+        0b01 => {
+            let displacement = (next_byte(bs)? as i8) as i16;
+            Loc::EAC(parse_r_m_field(r_m_bits, Some(displacement)))
+        }
+        0b10 => {
+            let displacement = consume_i16(bs)?;
+            Loc::EAC(parse_r_m_field(r_m_bits, Some(displacement)))
+        }
+        _ => panic!("unexpected MOD field: 0b_{:b}", mod_bits),
+    })
+}
+
+fn parse_imm_to_r_m(b: u8, bs: &mut Cursor) -> Result<Option<Op>, DecodeError> {
+    let is_mov = b >> (8 - MOV_OPCODE_LEN) == MOV_OPCODE;
+    let is_other_imm_to_r_m = b >> (8 - IMM_TO_R_M_OPCODE_LEN) == IMM_TO_R_M_OPCODE;
+    if !is_mov && !is_other_imm_to_r_m {
+        return Ok(None);
+    }
+
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    // XXXXXX: opcode
+    // byte 0   byte 1
+    // XXXXXXSW MOD|BINOP|R/M
+    //           2    3    3
+    let w = b0 & 0b_0000_0001 != 0; // is_wide
+                                    // SPECIAL CASE:
+                                    // for the MOV instruction, `s` can be considered as
+                                    // always 0
+    let s = !is_mov && (b0 & 0b_0000_0010 != 0); // is_sign_extended
+    let binop = BinOpCode::find((b1 >> 3) & 0b111);
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let r_m_bits = b1 & 0b_0000_0111;
+
+    let r_m_loc = parse_r_m_loc(bs, mod_bits, r_m_bits, w)?;
+    let src = if w && !s {
+        Loc::Imm16(consume_u16(bs)?)
+    } else if w && s {
+        // sign extending
+        Loc::Imm16((next_byte(bs)? as i8) as i16 as u16)
+    } else {
+        Loc::Imm8(next_byte(bs)?)
+    };
+
+    // the mov form only has the one encoding, with 000 in the middle
+    let params = match (is_mov, binop) {
+        (true, _) if b1 & 0b_0011_1000 == 0 => BinopParams::Mov,
+        (false, Some(binop)) => BinopParams::Op(binop),
+        _ => {
+            return Err(DecodeError::UnsupportedModRm {
+                opcode: b0,
+                mod_rm: b1,
+            })
+        }
+    };
+    Ok(Some(binop_to_op(params, src, r_m_loc)))
+}
+
+#[derive(Clone, Copy)]
+enum BinopParams {
+    Mov,
+    Op(BinOpCode),
+}
+
+impl BinopParams {
+    fn from(is_mov: bool, code: Option<BinOpCode>) -> Self {
+        match code {
+            Some(code) if !is_mov => Self::Op(code),
+            _ => Self::Mov,
+        }
+    }
+}
+
+fn binop_to_op(params: BinopParams, src: Loc, dst: Loc) -> Op {
+    match params {
+        BinopParams::Mov => Op::Mov(Mov { src, dst }),
+        BinopParams::Op(BinOpCode::Add) => Op::Add(Add { src, dst }),
+        BinopParams::Op(BinOpCode::Sub) => Op::Sub(Sub { src, dst }),
+        BinopParams::Op(BinOpCode::Cmp) => Op::Cmp(Cmp { src, dst }),
+        BinopParams::Op(BinOpCode::And) => Op::And(And { src, dst }),
+        BinopParams::Op(BinOpCode::Or) => Op::Or(Or { src, dst }),
+        BinopParams::Op(BinOpCode::Adc) => Op::Adc(Adc { src, dst }),
+        BinopParams::Op(BinOpCode::Sbb) => Op::Sbb(Sbb { src, dst }),
+        BinopParams::Op(BinOpCode::Xor) => Op::Xor(Xor { src, dst }),
+    }
+}
+
+fn consume_imm(bs: &mut Cursor, w: bool) -> Result<Loc, DecodeError> {
+    Ok(if w {
+        Loc::Imm16(consume_u16(bs)?)
+    } else {
+        Loc::Imm8(next_byte(bs)?)
+    })
+}
+
+fn consume_u16(bs: &mut Cursor) -> Result<u16, DecodeError> {
+    Ok(u16::from_le_bytes([next_byte(bs)?, next_byte(bs)?]))
+}
+
+fn consume_i16(bs: &mut Cursor) -> Result<i16, DecodeError> {
+    Ok(i16::from_le_bytes([next_byte(bs)?, next_byte(bs)?]))
+}
+
+fn consume_i8(bs: &mut Cursor) -> Result<i8, DecodeError> {
+    Ok(i8::from_le_bytes([next_byte(bs)?]))
+}
+
+fn next_byte(bs: &mut Cursor) -> Result<u8, DecodeError> {
+    bs.next().ok_or(DecodeError::UnexpectedEof)
+}
+
+fn parse_sreg_mov(bs: &mut Cursor) -> Result<Mov, DecodeError> {
+    // byte 0   byte 1
+    // 100011D0 MOD|0|SR|R/M
+    //           2  1 2   3
+    // D is 1 when the segment register is the destination
+    let b0 = next_byte(bs)?;
+    let b1 = next_byte(bs)?;
+    if b1 & 0b_0010_0000 != 0 {
+        return Err(DecodeError::UnsupportedModRm {
+            opcode: b0,
+            mod_rm: b1,
+        });
+    }
+    let d = b0 & 0b_0000_0010 != 0;
+    let mod_bits = (b1 & 0b_1100_0000) >> 6;
+    let sr_bits = (b1 & 0b_0001_1000) >> 3;
+    let r_m_bits = b1 & 0b_0000_0111;
+    let sreg = Loc::Reg(parse_sreg_field(sr_bits));
+    let r_m = parse_r_m_loc(bs, mod_bits, r_m_bits, true)?;
+    Ok(if d {
+        Mov {
+            src: r_m,
+            dst: sreg,
+        }
+    } else {
+        Mov {
+            src: sreg,
+            dst: r_m,
+        }
+    })
+}
+
+fn parse_sreg_field(sr: u8) -> RegIndex {
+    match sr {
+        0b00 => RegIndex::ES,
+        0b01 => RegIndex::CS,
+        0b10 => RegIndex::SS,
+        0b11 => RegIndex::DS,
+        _ => panic!("unexpected SR field: 0b_{:b}", sr),
+    }
+}
+
+fn decode_mov(byte: u8, bytes: &mut Cursor) -> Result<Option<Mov>, DecodeError> {
+    if byte >> 4 == 0b_1011 {
+        parse_imm_to_reg_mov(bytes).map(Some)
+    } else if byte >> 1 == 0b_101_0000 {
+        parse_mem_to_acc_mov(bytes).map(Some)
+    } else if byte >> 1 == 0b_101_0001 {
+        parse_acc_to_mem_mov(bytes).map(Some)
+    } else if byte & 0b_1111_1101 == 0b_1000_1100 {
+        parse_sreg_mov(bytes).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    Decoder::new(bytes)
+        .map(|next| next.map(|(inst, _)| inst))
+        .collect()
+}
+
+// returns an instruction, and number of bytes in that instruction
+pub fn decode_first_at(bytes: &[u8], ip: usize) -> Result<(Instruction, usize), DecodeError> {
+    let (inst, range) = Decoder::at(bytes, ip)
+        .next()
+        .unwrap_or(Err(DecodeError::UnexpectedEof))?;
+    Ok((inst, range.len()))
+}
+
+// `decode_first_at`, remembering what it found at each offset, since a loop
+// goes back to the same few instructions over and over
+pub struct DecodeCache {
+    entries: Vec<Option<(Instruction, usize)>>,
+}
+
+impl DecodeCache {
+    // `len` is how many bytes the program is, the offsets it can be asked for
+    pub fn new(len: usize) -> Self {
+        Self {
+            entries: vec![None; len],
+        }
+    }
+
+    // errors aren't kept, the sim stops at the first one anyway
+    pub fn decode_at(
+        &mut self,
+        bytes: &[u8],
+        ip: usize,
+    ) -> Result<(Instruction, usize), DecodeError> {
+        if let Some(Some(cached)) = self.entries.get(ip) {
+            return Ok(*cached);
+        }
+        let decoded = decode_first_at(bytes, ip)?;
+        if let Some(entry) = self.entries.get_mut(ip) {
+            *entry = Some(decoded);
+        }
+        Ok(decoded)
+    }
+
+    // for when the bytes at `range` change under it. anything that started
+    // up to an instruction's length before could have run into them
+    pub fn invalidate(&mut self, range: Range<usize>) {
+        let start = range.start.saturating_sub(MAX_INSTRUCTION_LEN - 1);
+        let end = range.end.min(self.entries.len());
+        for entry in self.entries.get_mut(start..end).unwrap_or_default() {
+            *entry = None;
+        }
+    }
+}
+
+// walks a slice an instruction at a time, along with the range of bytes each
+// one came from. stops after the first error, there's no telling where the
+// next instruction would start
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    is_failed: bool,
+    is_skipping_unknown: bool,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::at(bytes, 0)
+    }
+
+    // starting partway in, like at a jump target
+    pub fn at(bytes: &'a [u8], pos: usize) -> Self {
+        Self {
+            bytes,
+            pos,
+            is_failed: false,
+            is_skipping_unknown: false,
+        }
+    }
+
+    // instead of stopping, turn the first byte of anything that doesn't
+    // decode into `Op::Unknown` and try again from the next one,
+    // like a disassembler does with data mixed in with the code
+    pub fn skipping_unknown(mut self) -> Self {
+        self.is_skipping_unknown = true;
+        self
+    }
+
+    // where `err`, the last thing it returned, happened. it stays on the
+    // instruction that failed
+    pub fn locate(&self, err: DecodeError) -> DecodeError {
+        err.at(self.bytes, self.pos)
+    }
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Result<(Instruction, Range<usize>), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_failed {
+            return None;
+        }
+        let mut bytes = Cursor::new(self.bytes, self.pos);
+        let byte = bytes.peek()?;
+        match decode_next(byte, &mut bytes) {
+            Ok(op) => {
+                let start = self.pos;
+                self.pos = bytes.position();
+                Some(Ok((
+                    Instruction::decoded(op, self.pos - start),
+                    start..self.pos,
+                )))
+            }
+            Err(_) if self.is_skipping_unknown => {
+                let start = self.pos;
+                self.pos += 1;
+                Some(Ok((
+                    Instruction::decoded(Op::Unknown(byte), 1),
+                    start..self.pos,
+                )))
+            }
+            Err(err) => {
+                self.is_failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// the longest instruction there is, 6 bytes, with a segment prefix
+const MAX_INSTRUCTION_LEN: usize = 7;
+
+// `Decoder`, but pulling the bytes from a reader as it goes instead of
+// having them all up front. the ranges count from the start of the stream
+pub struct ReadDecoder<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    // how far into the stream `buf` starts
+    offset: usize,
+    is_eof: bool,
+    is_failed: bool,
+    is_skipping_unknown: bool,
+}
+
+impl<R: std::io::Read> ReadDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![],
+            pos: 0,
+            offset: 0,
+            is_eof: false,
+            is_failed: false,
+            is_skipping_unknown: false,
+        }
+    }
+
+    // see `Decoder::skipping_unknown`
+    pub fn skipping_unknown(mut self) -> Self {
+        self.is_skipping_unknown = true;
+        self
+    }
+
+    // see `Decoder::locate`. only what's still buffered is there to show,
+    // and a failed read has no instruction to point at
+    pub fn locate(&self, err: DecodeError) -> DecodeError {
+        match err {
+            DecodeError::Io(_) => err,
+            _ => err.at_in(&self.buf, self.pos, self.offset),
+        }
+    }
+
+    // makes sure a whole instruction is buffered, unless the input ends
+    // first, so one split across reads still decodes
+    fn fill(&mut self) -> std::io::Result<()> {
+        if self.buf.len() - self.pos >= MAX_INSTRUCTION_LEN {
+            return Ok(());
+        }
+        self.buf.drain(..self.pos);
+        self.offset += self.pos;
+        self.pos = 0;
+        let mut chunk = [0; 4096];
+        while !self.is_eof && self.buf.len() < MAX_INSTRUCTION_LEN {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => self.is_eof = true,
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: std::io::Read> Iterator for ReadDecoder<R> {
+    type Item = Result<(Instruction, Range<usize>), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_failed {
+            return None;
+        }
+        if let Err(err) = self.fill() {
+            self.is_failed = true;
+            return Some(Err(DecodeError::Io(err.kind())));
+        }
+        let mut decoder = Decoder::at(&self.buf, self.pos);
+        if self.is_skipping_unknown {
+            decoder = decoder.skipping_unknown();
+        }
+        match decoder.next()? {
+            Ok((inst, range)) => {
+                self.pos = range.end;
+                Some(Ok((
+                    inst,
+                    self.offset + range.start..self.offset + range.end,
+                )))
+            }
+            Err(err) => {
+                self.is_failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// which parser takes an instruction, going by its first byte. some of these
+// (the immediate group, f6, ff) look at the mod-reg-r/m byte to pick the
+// operation too
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Handler {
+    Unknown,
+    RmToRm,
+    ImmToAcc,
+    ImmToRm,
+    Mov,
+    Jump,
+    PushPop,
+    GroupF6,
+    Shift,
+    Test,
+    Xchg,
+    IncDec,
+    GroupFf,
+    CallRet,
+    InOut,
+    Adjust,
+    Hlt,
+    Wait,
+    Esc,
+    Direction,
+    Str,
+    RepPrefix,
+    SegmentPrefix,
+}
+
+impl Handler {
+    const fn for_opcode(b: u8) -> Self {
+        match b {
+            0x27 | 0x2f | 0x37 | 0x3f | 0xd4 | 0xd5 => Self::Adjust,
+            0x26 | 0x2e | 0x36 | 0x3e => Self::SegmentPrefix,
+            // the eight binops, each with four r/m forms and two accumulator
+            // ones. the two left in each row are segment pushes and pops
+            0x00..=0x3f => match b & 0b111 {
+                0b000..=0b011 => Self::RmToRm,
+                0b100 | 0b101 => Self::ImmToAcc,
+                _ => Self::Unknown,
+            },
+            0x40..=0x4f => Self::IncDec,
+            0x50..=0x5f | 0x8f | 0x9c | 0x9d => Self::PushPop,
+            0x70..=0x7f | 0xe0..=0xe3 => Self::Jump,
+            0x80..=0x83 | 0xc6 | 0xc7 => Self::ImmToRm,
+            0x84 | 0x85 | 0xa8 | 0xa9 => Self::Test,
+            0x86 | 0x87 | 0x90..=0x97 => Self::Xchg,
+            0x88..=0x8b => Self::RmToRm,
+            0x8c | 0x8e | 0xa0..=0xa3 | 0xb0..=0xbf => Self::Mov,
+            0xc2 | 0xc3 | 0xe8 => Self::CallRet,
+            0xd0..=0xd3 => Self::Shift,
+            0xe4..=0xe7 | 0xec..=0xef => Self::InOut,
+            0xa4..=0xa7 | 0xaa..=0xaf => Self::Str,
+            0xf2 | 0xf3 => Self::RepPrefix,
+            0xf4 => Self::Hlt,
+            0x9b => Self::Wait,
+            0xd8..=0xdf => Self::Esc,
+            0xfc | 0xfd => Self::Direction,
+            0xf6 | 0xf7 => Self::GroupF6,
+            0xfe | 0xff => Self::GroupFf,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+// the arms of `for_opcode` are tried in order, so an arm added above the
+// jumps could take some of them without anything else noticing
+const _: () = {
+    let mut i = 0;
+    while i < JumpType::ALL.len() {
+        assert!(matches!(
+            Handler::for_opcode(JumpType::ALL[i] as u8),
+            Handler::Jump
+        ));
+        i += 1;
+    }
+};
+
+static HANDLERS: [Handler; 256] = {
+    let mut handlers = [Handler::Unknown; 256];
+    let mut b = 0;
+    while b < handlers.len() {
+        handlers[b] = Handler::for_opcode(b as u8);
+        b += 1;
+    }
+    handlers
+};
+
+// what came before the instruction itself, along with the byte it came from
+// for when it turns out not to fit
+#[derive(Default)]
+struct Prefixes {
+    segment: Option<(u8, Reg)>,
+    rep: Option<(u8, Rep)>,
+}
+
+impl Prefixes {
+    // one of each at most, in either order
+    fn take(&mut self, byte: u8) -> Result<(), DecodeError> {
+        match HANDLERS[byte as usize] {
+            Handler::SegmentPrefix => {
+                if let Some((first, _)) = self.segment {
+                    return Err(DecodeError::StrayPrefix(first));
+                }
+                // 001SR110, with SR picking es, cs, ss or ds
+                let segment = match (byte >> 3) & 0b11 {
+                    0b00 => Reg::ES,
+                    0b01 => Reg::CS,
+                    0b10 => Reg::SS,
+                    _ => Reg::DS,
+                };
+                self.segment = Some((byte, segment));
+            }
+            Handler::RepPrefix => {
+                if let Some((first, _)) = self.rep {
+                    return Err(DecodeError::StrayPrefix(first));
+                }
+                // 1111001|Z, with Z set for rep/repe
+                let rep = if byte & 1 == 1 { Rep::Rep } else { Rep::Repne };
+                self.rep = Some((byte, rep));
+            }
+            _ => unreachable!("{byte:#04x} isn't a prefix"),
+        }
+        Ok(())
+    }
+
+    // a segment override goes on the memory operand, or a string op's
+    // source, and rep only goes on string ops
+    fn apply(&self, mut inst: Op) -> Result<Op, DecodeError> {
+        if let Some((byte, segment)) = self.segment {
+            match &mut inst {
+                Op::Str(s) => s.segment = Some(segment),
+                inst => match inst.eac_mut() {
+                    Some(eac) => eac.segment = Some(segment),
+                    None => return Err(DecodeError::StrayPrefix(byte)),
+                },
+            }
+        }
+        if let Some((byte, rep)) = self.rep {
+            match &mut inst {
+                Op::Str(s) => s.rep = Some(rep),
+                _ => return Err(DecodeError::StrayPrefix(byte)),
+            }
+        }
+        Ok(inst)
+    }
+}
+
+fn decode_next(mut byte: u8, bytes: &mut Cursor) -> Result<Op, DecodeError> {
+    let mut prefixes = Prefixes::default();
+    while matches!(
+        HANDLERS[byte as usize],
+        Handler::SegmentPrefix | Handler::RepPrefix
+    ) {
+        prefixes.take(byte)?;
+        bytes.next();
+        byte = bytes.peek().ok_or(DecodeError::UnexpectedEof)?;
+    }
+    decode_base(byte, bytes, &prefixes)
+}
+
+// the instruction after any prefixes, which get applied to it once it's
+// decoded
+fn decode_base(byte: u8, bytes: &mut Cursor, prefixes: &Prefixes) -> Result<Op, DecodeError> {
+    let inst = match HANDLERS[byte as usize] {
+        Handler::Unknown => None,
+        Handler::RmToRm => parse_r_m_to_r_m(byte, bytes)?,
+        Handler::ImmToAcc => parse_imm_to_acc(byte, bytes)?,
+        Handler::ImmToRm => parse_imm_to_r_m(byte, bytes)?,
+        Handler::Mov => decode_mov(byte, bytes)?.map(Op::Mov),
+        Handler::Jump => try_parse_jump(byte, bytes)?.map(Op::Jump),
+        Handler::PushPop => try_parse_push_pop(byte, bytes)?,
+        Handler::GroupF6 => try_parse_group_f6(byte, bytes)?,
+        Handler::Shift => try_parse_shift(byte, bytes)?,
+        Handler::Test => try_parse_test(byte, bytes)?,
+        Handler::Xchg => try_parse_xchg(byte, bytes)?,
+        Handler::IncDec => try_parse_inc_dec(byte, bytes)?,
+        Handler::GroupFf => try_parse_group_ff(byte, bytes)?,
+        Handler::CallRet => try_parse_call_ret(byte, bytes)?,
+        Handler::InOut => try_parse_in_out(byte, bytes)?,
+        Handler::Adjust => try_parse_adjust(byte, bytes)?,
+        Handler::Hlt => {
+            bytes.next();
+            Some(Op::Hlt)
+        }
+        Handler::Wait => {
+            bytes.next();
+            Some(Op::Wait)
+        }
+        Handler::Esc => try_parse_esc(byte, bytes)?,
+        Handler::Str => try_parse_str(byte, bytes)?,
+        // 1111110|D
+        Handler::Direction => {
+            bytes.next();
+            Some(if byte & 1 == 0 { Op::Cld } else { Op::Std })
+        }
+        Handler::SegmentPrefix | Handler::RepPrefix => {
+            unreachable!("prefixes are taken by decode_next")
+        }
+    };
+    // the parsers still check the opcode they're given, so a miss here is
+    // the table being out of step with one of them
+    prefixes.apply(inst.ok_or(DecodeError::UnknownOpcode(byte))?)
+}
@@ -0,0 +1,73 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{DecodeError, Decoder, Instruction, Op, Syntax};
+
+// two passes: decode everything with its address first, so that every jump
+// target is known before printing and can be given a name like the course
+// listings use. `skip_unknown` keeps going past bytes that don't decode, see
+// `Decoder::skipping_unknown`
+pub fn disassemble(
+    bytes: &[u8],
+    skip_unknown: bool,
+    syntax: Syntax,
+) -> Result<Vec<String>, DecodeError> {
+    let mut decoder = Decoder::new(bytes);
+    if skip_unknown {
+        decoder = decoder.skipping_unknown();
+    }
+    let insts = decoder
+        .by_ref()
+        .map(|next| next.map(|(inst, range)| (range.start, inst)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| decoder.locate(err))?;
+
+    // a target in the middle of an instruction (or outside the program) has
+    // nowhere to put a label, so those jumps keep the numeric form. landing
+    // just past the end is fine, the label goes on the last line
+    let is_start = |target: isize| {
+        let Ok(target) = usize::try_from(target) else {
+            return false;
+        };
+        target == bytes.len() || insts.binary_search_by_key(&target, |(a, _)| *a).is_ok()
+    };
+    let targets = insts
+        .iter()
+        .filter_map(|(addr, inst)| jump_target(*addr, inst))
+        .filter(|target| is_start(*target))
+        .map(|target| target as usize)
+        .collect::<BTreeSet<_>>();
+    let labels = targets
+        .into_iter()
+        .enumerate()
+        .map(|(i, target)| (target, format!("label_{i}")))
+        .collect::<HashMap<_, _>>();
+
+    let mut lines = vec![];
+    for (addr, inst) in &insts {
+        if let Some(label) = labels.get(addr) {
+            lines.push(format!("{label}:"));
+        }
+        let label = jump_target(*addr, inst)
+            .and_then(|target| usize::try_from(target).ok())
+            .and_then(|target| labels.get(&target));
+        match (&inst.op, label) {
+            (Op::Jump(jump), Some(label)) => lines.push(format!("{} {label}", jump.typ.asm())),
+            (Op::Call(_), Some(label)) => lines.push(format!("call {label}")),
+            _ => lines.push(syntax.asm(inst)),
+        }
+    }
+    if let Some(label) = labels.get(&bytes.len()) {
+        lines.push(format!("{label}:"));
+    }
+    Ok(lines)
+}
+
+// where a jump or call at `addr` goes, which can be anywhere (even negative)
+// if the bytes weren't really code
+pub fn jump_target(addr: usize, inst: &Instruction) -> Option<isize> {
+    match &inst.op {
+        Op::Jump(jump) => Some((addr + jump.size()) as isize + jump.offset as isize),
+        Op::Call(call) => Some((addr + call.size()) as isize + call.offset as isize),
+        _ => None,
+    }
+}
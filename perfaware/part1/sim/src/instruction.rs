@@ -0,0 +1,1472 @@
+use std::fmt;
+
+// an op as it was encoded. the same op can often be encoded more than one
+// way, `mov ax, [1000]` is a1 e8 03 or 8b 06 e8 03, so the size comes from
+// the bytes it was decoded from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: Op,
+    size: u8,
+}
+
+impl Instruction {
+    pub(crate) fn decoded(op: Op, size: usize) -> Self {
+        Self {
+            op,
+            size: size as u8,
+        }
+    }
+
+    pub fn asm(&self) -> String {
+        self.op.asm()
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.op.mnemonic()
+    }
+
+    // how many bytes it took up, prefixes and all
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+}
+
+// one that was never encoded, like the assembler's, is taken to be the
+// shortest encoding, which is the one nasm picks
+impl From<Op> for Instruction {
+    fn from(op: Op) -> Self {
+        Self {
+            op,
+            size: op.shortest_size() as u8,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.op)
+    }
+}
+
+// what an instruction does, whichever way it's encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Mov(Mov),
+    Jump(Jump),
+    Add(Add),
+    Adc(Adc),
+    Sub(Sub),
+    Sbb(Sbb),
+    Cmp(Cmp),
+    And(And),
+    Or(Or),
+    Xor(Xor),
+    Test(Test),
+    Xchg(Xchg),
+    Inc(Inc),
+    Dec(Dec),
+    Push(Push),
+    Pop(Pop),
+    Pushf,
+    Popf,
+    Cld,
+    Std,
+    Call(Call),
+    CallIndirect(Indirect),
+    JmpIndirect(Indirect),
+    Ret(Ret),
+    Mul(Mul),
+    Div(Div),
+    Shift(Shift),
+    Str(Str),
+    Hlt,
+    Wait,
+    Esc(Esc),
+    In(In),
+    Out(Out),
+    Adjust(Adjust),
+    // a byte that doesn't start anything we decode, kept as data so the
+    // rest can still be disassembled. only `Decoder::skipping_unknown` makes
+    // these
+    Unknown(u8),
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mov(m) => write!(f, "{m}"),
+            Self::Jump(j) => write!(f, "{j}"),
+            Self::Add(a) => write!(f, "{a}"),
+            Self::Adc(a) => write!(f, "{a}"),
+            Self::Sub(s) => write!(f, "{s}"),
+            Self::Sbb(s) => write!(f, "{s}"),
+            Self::Cmp(c) => write!(f, "{c}"),
+            Self::And(a) => write!(f, "{a}"),
+            Self::Or(o) => write!(f, "{o}"),
+            Self::Xor(x) => write!(f, "{x}"),
+            Self::Test(t) => write!(f, "{t}"),
+            Self::Xchg(x) => write!(f, "{x}"),
+            Self::Inc(i) => write!(f, "{i}"),
+            Self::Dec(d) => write!(f, "{d}"),
+            Self::Push(p) => write!(f, "{p}"),
+            Self::Pop(p) => write!(f, "{p}"),
+            Self::Pushf => f.write_str("pushf"),
+            Self::Popf => f.write_str("popf"),
+            Self::Cld => f.write_str("cld"),
+            Self::Std => f.write_str("std"),
+            Self::Hlt => f.write_str("hlt"),
+            Self::Wait => f.write_str("wait"),
+            Self::Esc(e) => write!(f, "{e}"),
+            Self::In(i) => write!(f, "{i}"),
+            Self::Out(o) => write!(f, "{o}"),
+            Self::Adjust(a) => write!(f, "{a}"),
+            Self::Call(c) => write!(f, "{c}"),
+            Self::CallIndirect(i) => i.write_asm(f, "call"),
+            Self::JmpIndirect(i) => i.write_asm(f, "jmp"),
+            Self::Ret(r) => write!(f, "{r}"),
+            Self::Mul(m) => write!(f, "{m}"),
+            Self::Div(d) => write!(f, "{d}"),
+            Self::Shift(s) => write!(f, "{s}"),
+            Self::Str(s) => write!(f, "{s}"),
+            Self::Unknown(b) => write!(f, "db {:#04x}", b),
+        }
+    }
+}
+
+impl Op {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // the first word of `asm`, without the prefixes. string ops are the
+    // op, whatever rep is in front of them
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Mov(_) => "mov",
+            Self::Jump(j) => j.typ.asm(),
+            Self::Add(_) => "add",
+            Self::Adc(_) => "adc",
+            Self::Sub(_) => "sub",
+            Self::Sbb(_) => "sbb",
+            Self::Cmp(_) => "cmp",
+            Self::And(_) => "and",
+            Self::Or(_) => "or",
+            Self::Xor(_) => "xor",
+            Self::Test(_) => "test",
+            Self::Xchg(_) => "xchg",
+            Self::Inc(_) => "inc",
+            Self::Dec(_) => "dec",
+            Self::Push(_) => "push",
+            Self::Pop(_) => "pop",
+            Self::Pushf => "pushf",
+            Self::Popf => "popf",
+            Self::Cld => "cld",
+            Self::Std => "std",
+            Self::Hlt => "hlt",
+            Self::Wait => "wait",
+            Self::Esc(_) => "esc",
+            Self::In(_) => "in",
+            Self::Out(_) => "out",
+            Self::Adjust(a) => a.mnemonic(),
+            Self::Call(_) | Self::CallIndirect(_) => "call",
+            Self::JmpIndirect(_) => "jmp",
+            Self::Ret(_) => "ret",
+            Self::Mul(m) => {
+                if m.signed {
+                    "imul"
+                } else {
+                    "mul"
+                }
+            }
+            Self::Div(d) => {
+                if d.signed {
+                    "idiv"
+                } else {
+                    "div"
+                }
+            }
+            Self::Shift(s) => s.op.asm(),
+            Self::Str(s) => s.mnemonic(),
+            Self::Unknown(_) => "db",
+        }
+    }
+
+    // the length of the shortest of its encodings
+    pub fn shortest_size(&self) -> usize {
+        match self {
+            Self::Mov(m) => m.size(),
+            Self::Jump(j) => j.size(),
+            Self::Add(Add { src, dst })
+            | Self::Adc(Adc { src, dst })
+            | Self::Sub(Sub { src, dst })
+            | Self::Sbb(Sbb { src, dst })
+            | Self::Cmp(Cmp { src, dst })
+            | Self::And(And { src, dst })
+            | Self::Or(Or { src, dst })
+            | Self::Xor(Xor { src, dst }) => binop_size(*dst, *src),
+            Self::Test(t) => t.size(),
+            Self::Xchg(x) => x.size(),
+            Self::Inc(Inc { dst, width }) | Self::Dec(Dec { dst, width }) => match (dst, width) {
+                (Loc::Reg(_), Width::Word) => 1,
+                _ => 2 + dst.encoded_size(),
+            },
+            Self::Push(Push { src: loc }) | Self::Pop(Pop { dst: loc }) => match loc {
+                Loc::Reg(_) => 1,
+                _ => 2 + loc.encoded_size(),
+            },
+            Self::Pushf
+            | Self::Popf
+            | Self::Cld
+            | Self::Std
+            | Self::Hlt
+            | Self::Wait
+            | Self::Unknown(_) => 1,
+            Self::In(In { port, .. }) | Self::Out(Out { port, .. }) => port.size(),
+            Self::Adjust(a) => a.size(),
+            Self::Call(c) => c.size(),
+            Self::CallIndirect(i) | Self::JmpIndirect(i) => 2 + i.target.encoded_size(),
+            Self::Ret(r) => r.size(),
+            Self::Mul(Mul { src: loc, .. })
+            | Self::Div(Div { src: loc, .. })
+            | Self::Shift(Shift { dst: loc, .. })
+            | Self::Esc(Esc { src: loc, .. }) => 2 + loc.encoded_size(),
+            Self::Str(s) => 1 + s.rep.is_some() as usize + s.segment.is_some() as usize,
+        }
+    }
+}
+
+impl Op {
+    // the memory operand, which a segment prefix applies to. no instruction
+    // has more than one
+    pub(crate) fn eac_mut(&mut self) -> Option<&mut EAC> {
+        let loc = match self {
+            Self::Mov(Mov { dst, src })
+            | Self::Add(Add { dst, src })
+            | Self::Adc(Adc { dst, src })
+            | Self::Sub(Sub { dst, src })
+            | Self::Sbb(Sbb { dst, src })
+            | Self::Cmp(Cmp { dst, src })
+            | Self::And(And { dst, src })
+            | Self::Or(Or { dst, src })
+            | Self::Xor(Xor { dst, src })
+            | Self::Test(Test { dst, src })
+            | Self::Xchg(Xchg { dst, src }) => {
+                if matches!(dst, Loc::EAC(_)) {
+                    dst
+                } else {
+                    src
+                }
+            }
+            Self::Inc(Inc { dst: loc, .. })
+            | Self::Dec(Dec { dst: loc, .. })
+            | Self::Push(Push { src: loc })
+            | Self::Pop(Pop { dst: loc })
+            | Self::Mul(Mul { src: loc, .. })
+            | Self::Div(Div { src: loc, .. })
+            | Self::Shift(Shift { dst: loc, .. })
+            | Self::Esc(Esc { src: loc, .. })
+            | Self::CallIndirect(Indirect { target: loc, .. })
+            | Self::JmpIndirect(Indirect { target: loc, .. }) => loc,
+            _ => return None,
+        };
+        match loc {
+            Loc::EAC(eac) => Some(eac),
+            _ => None,
+        }
+    }
+}
+
+// opcode + mod r/m, plus whatever the operands add. the accumulator gets
+// its own short form with the mod r/m byte dropped, and word immediates
+// that fit in a byte get sign extended from one
+fn binop_size(dst: Loc, src: Loc) -> usize {
+    match (dst, src) {
+        (Loc::Reg(reg), Loc::Imm8(_) | Loc::Imm16(_)) if reg.is_acc() => 1 + src.encoded_size(),
+        (_, Loc::Imm16(n)) if (n as i16) == (n as i8) as i16 => 2 + dst.encoded_size() + 1,
+        _ => 2 + dst.encoded_size() + src.encoded_size(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Reg {
+    A = 0,
+    B,
+    C,
+    D,
+    DI,
+    SI,
+    SP,
+    BP,
+    IP,
+    ES,
+    CS,
+    SS,
+    DS,
+}
+
+impl Reg {
+    pub(crate) const fn num() -> usize {
+        std::mem::variant_count::<Self>()
+    }
+
+    // the full 16 bit register
+    pub fn asm(&self) -> &'static str {
+        match self {
+            Self::A => "ax",
+            Self::B => "bx",
+            Self::C => "cx",
+            Self::D => "dx",
+            Self::DI => "di",
+            Self::SI => "si",
+            Self::SP => "sp",
+            Self::BP => "bp",
+            Self::IP => "ip",
+            Self::ES => "es",
+            Self::CS => "cs",
+            Self::SS => "ss",
+            Self::DS => "ds",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Jump {
+    pub typ: JumpType,
+    pub offset: i8,
+}
+
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = self.typ.asm();
+        // nasm is weird, and takes the offset for BEFORE the instruction
+        // instead of after, so we have to mix in the instruction size
+        let nasm_offset = self.size() as i16 + self.offset as i16;
+        if nasm_offset >= 0 {
+            write!(f, "{mnemonic} $+{nasm_offset}")
+        } else {
+            write!(f, "{mnemonic} ${nasm_offset}")
+        }
+    }
+}
+
+impl Jump {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // for now, they're all 2, see page 168 in the intel 8086 manual
+    pub fn size(&self) -> usize {
+        2
+    }
+}
+
+// near and direct, so the target is relative to the next instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Call {
+    pub offset: i16,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // same as jumps, nasm wants the offset from before the instruction
+        let nasm_offset = self.size() as i32 + self.offset as i32;
+        if nasm_offset >= 0 {
+            write!(f, "call $+{nasm_offset}")
+        } else {
+            write!(f, "call ${nasm_offset}")
+        }
+    }
+}
+
+impl Call {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn size(&self) -> usize {
+        3
+    }
+}
+
+// a call or jmp through a register or memory, instead of to an offset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Indirect {
+    pub target: Loc,
+    // the target is a 4 byte pointer in memory, the new ip and then the new
+    // cs, rather than just the new ip
+    pub far: bool,
+}
+
+impl Indirect {
+    pub fn asm(&self, mnemonic: &str) -> String {
+        fmt::from_fn(|f| self.write_asm(f, mnemonic)).to_string()
+    }
+
+    fn write_asm(&self, f: &mut fmt::Formatter, mnemonic: &str) -> fmt::Result {
+        match self.target {
+            Loc::EAC(eac) if self.far => write!(f, "{mnemonic} far {eac}"),
+            target => write_unary(f, mnemonic, target, Width::Word),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ret {
+    // bytes to drop off the stack after popping ip. `ret 0` is its own
+    // encoding, so this isn't just 0 for a plain ret
+    pub pop: Option<u16>,
+}
+
+impl fmt::Display for Ret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pop {
+            Some(n) => write!(f, "ret {n}"),
+            None => f.write_str("ret"),
+        }
+    }
+}
+
+impl Ret {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn size(&self) -> usize {
+        match self.pop {
+            Some(_) => 3,
+            None => 1,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JumpType {
+    Jnz = 0b_0111_0101, // also stands for Jne
+    Je = 0b_0111_0100,
+    Jl = 0b_0111_1100,
+    Jle = 0b_0111_1110,
+    Jb = 0b_0111_0010,
+    Jbe = 0b_0111_0110,
+    Jp = 0b_0111_1010,
+    Jo = 0b_0111_0000,
+    Js = 0b_0111_1000,
+    Jnl = 0b_0111_1101,
+    Jg = 0b_0111_1111,
+    Jnb = 0b_0111_0011,
+    Ja = 0b_0111_0111,
+    Jnp = 0b_0111_1011,
+    Jno = 0b_0111_0001,
+    Jns = 0b_0111_1001,
+    Loop = 0b_1110_0010,
+    Loopz = 0b_1110_0001,
+    Loopnz = 0b_1110_0000,
+    Jcxz = 0b_1110_0011,
+}
+
+impl JumpType {
+    pub(crate) const ALL: [Self; 20] = [
+        Self::Jnz,
+        Self::Je,
+        Self::Jl,
+        Self::Jle,
+        Self::Jb,
+        Self::Jbe,
+        Self::Jp,
+        Self::Jo,
+        Self::Js,
+        Self::Jnl,
+        Self::Jg,
+        Self::Jnb,
+        Self::Ja,
+        Self::Jnp,
+        Self::Jno,
+        Self::Jns,
+        Self::Loop,
+        Self::Loopz,
+        Self::Loopnz,
+        Self::Jcxz,
+    ];
+
+    pub(crate) fn find(inst: u8) -> Option<Self> {
+        Self::ALL.iter().find(|b| **b as u8 == inst).copied()
+    }
+
+    pub fn asm(&self) -> &'static str {
+        match self {
+            Self::Jnz => "jnz",
+            Self::Je => "je",
+            Self::Jl => "jl",
+            Self::Jle => "jle",
+            Self::Jb => "jb",
+            Self::Jbe => "jbe",
+            Self::Jp => "jp",
+            Self::Jo => "jo",
+            Self::Js => "js",
+            Self::Jnl => "jnl",
+            Self::Jg => "jg",
+            Self::Jnb => "jnb",
+            Self::Ja => "ja",
+            Self::Jnp => "jnp",
+            Self::Jno => "jno",
+            Self::Jns => "jns",
+            Self::Loop => "loop",
+            Self::Loopz => "loopz",
+            Self::Loopnz => "loopnz",
+            Self::Jcxz => "jcxz",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mov {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Mov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "mov", self.dst, self.src)
+    }
+}
+
+impl Mov {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn size(&self) -> usize {
+        match (self.dst, self.src) {
+            // the immediate goes straight after the opcode, which has the
+            // register baked into it
+            (Loc::Reg(_), Loc::Imm8(_) | Loc::Imm16(_)) => 1 + self.src.encoded_size(),
+            (Loc::Reg(reg), Loc::EAC(eac)) | (Loc::EAC(eac), Loc::Reg(reg))
+                if reg.is_acc() && matches!(eac.base, EABase::DirectAddr(_)) =>
+            {
+                3 + eac.segment.is_some() as usize
+            }
+            (dst, src) => 2 + dst.encoded_size() + src.encoded_size(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Add {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Add {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "add", self.dst, self.src)
+    }
+}
+
+impl Add {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+// add, plus one more if CF is set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Adc {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Adc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "adc", self.dst, self.src)
+    }
+}
+
+impl Adc {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sub {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Sub {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "sub", self.dst, self.src)
+    }
+}
+
+impl Sub {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+// sub, borrowing one more if CF is set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sbb {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Sbb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "sbb", self.dst, self.src)
+    }
+}
+
+impl Sbb {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cmp {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "cmp", self.dst, self.src)
+    }
+}
+
+impl Cmp {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct And {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for And {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "and", self.dst, self.src)
+    }
+}
+
+impl And {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Or {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Or {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "or", self.dst, self.src)
+    }
+}
+
+impl Or {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xor {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Xor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "xor", self.dst, self.src)
+    }
+}
+
+impl Xor {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Test {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "test", self.dst, self.src)
+    }
+}
+
+impl Test {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // unlike the other binops, there's no sign extended immediate form
+    pub fn size(&self) -> usize {
+        match (self.dst, self.src) {
+            (Loc::Reg(reg), Loc::Imm8(_) | Loc::Imm16(_)) if reg.is_acc() => {
+                1 + self.src.encoded_size()
+            }
+            (dst, src) => 2 + dst.encoded_size() + src.encoded_size(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xchg {
+    pub src: Loc,
+    pub dst: Loc,
+}
+
+impl fmt::Display for Xchg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_binop(f, "xchg", self.dst, self.src)
+    }
+}
+
+impl Xchg {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn size(&self) -> usize {
+        match (self.dst, self.src) {
+            (Loc::Reg(a), Loc::Reg(b))
+                if matches!(a.region, Region::Xtended) && (a.is_acc() || b.is_acc()) =>
+            {
+                1
+            }
+            (dst, src) => 2 + dst.encoded_size() + src.encoded_size(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Inc {
+    pub dst: Loc,
+    pub width: Width,
+}
+
+impl fmt::Display for Inc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_unary(f, "inc", self.dst, self.width)
+    }
+}
+
+impl Inc {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dec {
+    pub dst: Loc,
+    pub width: Width,
+}
+
+impl fmt::Display for Dec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_unary(f, "dec", self.dst, self.width)
+    }
+}
+
+impl Dec {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Push {
+    pub src: Loc,
+}
+
+impl fmt::Display for Push {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_unary(f, "push", self.src, Width::Word)
+    }
+}
+
+impl Push {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pop {
+    pub dst: Loc,
+}
+
+impl fmt::Display for Pop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_unary(f, "pop", self.dst, Width::Word)
+    }
+}
+
+impl Pop {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+// the accumulator is always the other side, so only its width is needed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct In {
+    pub port: Port,
+    pub width: Width,
+}
+
+impl fmt::Display for In {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in {}, {}", acc_asm(self.width), self.port)
+    }
+}
+
+impl In {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Out {
+    pub port: Port,
+    pub width: Width,
+}
+
+impl fmt::Display for Out {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "out {}, {}", self.port, acc_asm(self.width))
+    }
+}
+
+impl Out {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Port {
+    Fixed(u8),
+    Dx,
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fixed(n) => write!(f, "{n}"),
+            Self::Dx => f.write_str("dx"),
+        }
+    }
+}
+
+impl Port {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // the fixed port is a byte after the opcode
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Fixed(_) => 2,
+            Self::Dx => 1,
+        }
+    }
+}
+
+// the BCD fixups, which work on AL (and AH for the ASCII ones) after an
+// add, sub, mul or before a div
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Adjust {
+    Daa,
+    Das,
+    Aaa,
+    Aas,
+    // the base is a byte after the opcode. it's always 10 to the assembler,
+    // but the 8086 takes whatever is there
+    Aam(u8),
+    Aad(u8),
+}
+
+impl fmt::Display for Adjust {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // the base is only written when it isn't the usual 10
+            Self::Aam(base) | Self::Aad(base) if *base != 10 => {
+                write!(f, "{} {base}", self.mnemonic())
+            }
+            _ => f.write_str(self.mnemonic()),
+        }
+    }
+}
+
+impl Adjust {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Daa => "daa",
+            Self::Das => "das",
+            Self::Aaa => "aaa",
+            Self::Aas => "aas",
+            Self::Aam(_) => "aam",
+            Self::Aad(_) => "aad",
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Aam(_) | Self::Aad(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+fn acc_asm(width: Width) -> &'static str {
+    match width {
+        Width::Byte => "al",
+        Width::Word => "ax",
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mul {
+    pub src: Loc,
+    pub width: Width,
+    pub signed: bool,
+}
+
+impl fmt::Display for Mul {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = if self.signed { "imul" } else { "mul" };
+        write_unary(f, mnemonic, self.src, self.width)
+    }
+}
+
+impl Mul {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Div {
+    pub src: Loc,
+    pub width: Width,
+    pub signed: bool,
+}
+
+impl fmt::Display for Div {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = if self.signed { "idiv" } else { "div" };
+        write_unary(f, mnemonic, self.src, self.width)
+    }
+}
+
+impl Div {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shift {
+    pub op: ShiftOp,
+    pub dst: Loc,
+    pub width: Width,
+    pub by_cl: bool, // otherwise by 1
+}
+
+impl fmt::Display for Shift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = if self.by_cl { "cl" } else { "1" };
+        write_unary(f, self.op.asm(), self.dst, self.width)?;
+        write!(f, ", {count}")
+    }
+}
+
+impl Shift {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShiftOp {
+    Rol = 0b000,
+    Ror = 0b001,
+    Rcl = 0b010,
+    Rcr = 0b011,
+    Shl = 0b100, // also stands for Sal
+    Shr = 0b101,
+    Sar = 0b111,
+}
+
+impl ShiftOp {
+    const ALL: [Self; 7] = [
+        Self::Rol,
+        Self::Ror,
+        Self::Rcl,
+        Self::Rcr,
+        Self::Shl,
+        Self::Shr,
+        Self::Sar,
+    ];
+
+    pub(crate) fn find(op: u8) -> Option<Self> {
+        Self::ALL.iter().find(|o| **o as u8 == op).copied()
+    }
+
+    pub fn asm(&self) -> &'static str {
+        match self {
+            Self::Rol => "rol",
+            Self::Ror => "ror",
+            Self::Rcl => "rcl",
+            Self::Rcr => "rcr",
+            Self::Shl => "shl",
+            Self::Shr => "shr",
+            Self::Sar => "sar",
+        }
+    }
+}
+
+// hands an instruction to a coprocessor like the 8087, which reads the
+// operand off the bus. `opcode` is the 6 bits of the instruction that say
+// what to do with it, which only the coprocessor knows the meaning of. nasm
+// has no esc, this is the manual's way of writing it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Esc {
+    pub opcode: u8,
+    pub src: Loc,
+}
+
+impl fmt::Display for Esc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "esc {}, {}", self.opcode, self.src)
+    }
+}
+
+impl Esc {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+}
+
+// the string instructions, which work on [si] and [di] and step them
+// afterwards. with a rep prefix they go round cx times
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Str {
+    pub op: StrOp,
+    pub width: Width,
+    pub rep: Option<Rep>,
+    // instead of ds for [si]. es:di can't be overridden, so stos and scas
+    // ignore it
+    pub segment: Option<Reg>,
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rep = match (self.rep, self.op.compares()) {
+            (None, _) => "",
+            (Some(Rep::Rep), false) => "rep ",
+            (Some(Rep::Rep), true) => "repe ",
+            (Some(Rep::Repne), _) => "repne ",
+        };
+        f.write_str(rep)?;
+        if let Some(reg) = self.segment {
+            write!(f, "{} ", reg.asm())?;
+        }
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl Str {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // the op with its size on the end
+    pub fn mnemonic(&self) -> &'static str {
+        match (self.op, self.width) {
+            (StrOp::Movs, Width::Byte) => "movsb",
+            (StrOp::Movs, Width::Word) => "movsw",
+            (StrOp::Cmps, Width::Byte) => "cmpsb",
+            (StrOp::Cmps, Width::Word) => "cmpsw",
+            (StrOp::Stos, Width::Byte) => "stosb",
+            (StrOp::Stos, Width::Word) => "stosw",
+            (StrOp::Lods, Width::Byte) => "lodsb",
+            (StrOp::Lods, Width::Word) => "lodsw",
+            (StrOp::Scas, Width::Byte) => "scasb",
+            (StrOp::Scas, Width::Word) => "scasw",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrOp {
+    Movs,
+    Cmps,
+    Stos,
+    Lods,
+    Scas,
+}
+
+impl StrOp {
+    pub fn asm(&self) -> &'static str {
+        match self {
+            Self::Movs => "movs",
+            Self::Cmps => "cmps",
+            Self::Stos => "stos",
+            Self::Lods => "lods",
+            Self::Scas => "scas",
+        }
+    }
+
+    // the ones that set the flags, which repe and repne stop on
+    pub fn compares(&self) -> bool {
+        matches!(self, Self::Cmps | Self::Scas)
+    }
+}
+
+// f3 is rep, or repe in front of a compare, which is the same thing. f2 is
+// repne
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rep {
+    Rep,
+    Repne,
+}
+
+// a register on either side tells nasm the size, so the keyword is only
+// needed to go between memory and an immediate
+fn write_binop(f: &mut fmt::Formatter, mnemonic: &str, dst: Loc, src: Loc) -> fmt::Result {
+    match (dst, src) {
+        (Loc::EAC(eac), Loc::Imm8(n)) => write!(f, "{mnemonic} byte {eac}, {n}"),
+        (Loc::EAC(eac), Loc::Imm16(n)) => write!(f, "{mnemonic} word {eac}, {n}"),
+        _ => write!(f, "{mnemonic} {dst}, {src}"),
+    }
+}
+
+// with a single operand there's nothing else to infer the size from, so
+// memory operands need the keyword
+fn write_unary(f: &mut fmt::Formatter, mnemonic: &str, loc: Loc, width: Width) -> fmt::Result {
+    match loc {
+        Loc::EAC(eac) => write!(f, "{mnemonic} {} {eac}", width.keyword()),
+        loc => write!(f, "{mnemonic} {loc}"),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Loc {
+    Reg(RegIndex),
+    EAC(EAC),
+    Imm8(u8),   // this is only applicable when Loc is a src
+    Imm16(u16), // this is only applicable when Loc is a src
+}
+
+impl Loc {
+    // memory operands don't know their own size, but a binop always has a
+    // register or an immediate on one side to take it from
+    pub(crate) fn op_width(dst: Loc, src: Loc) -> Width {
+        dst.width().or(src.width()).unwrap_or(Width::Word)
+    }
+
+    pub(crate) fn width(&self) -> Option<Width> {
+        match self {
+            Self::Reg(reg) => Some(reg.region.width()),
+            Self::Imm8(_) => Some(Width::Byte),
+            Self::Imm16(_) => Some(Width::Word),
+            Self::EAC(_) => None,
+        }
+    }
+
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // bytes the operand takes up after the opcode and mod r/m, plus the
+    // segment prefix in front of the opcode if there is one
+    pub(crate) fn encoded_size(&self) -> usize {
+        match self {
+            Self::Reg(_) => 0,
+            Self::Imm8(_) => 1,
+            Self::Imm16(_) => 2,
+            Self::EAC(eac) => eac.displacement_size() + eac.segment.is_some() as usize,
+        }
+    }
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Reg(reg) => write!(f, "{reg}"),
+            Self::Imm8(n) => write!(f, "{n}"),
+            Self::Imm16(n) => write!(f, "{n}"),
+            Self::EAC(eac) => write!(f, "{eac}"),
+        }
+    }
+}
+
+// Effective Address Calculation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EAC {
+    pub base: EABase,
+    pub displacement: Option<i16>, // can be either 0, 8, or 16 bits
+    // from a prefix on the instruction, otherwise it's the segment that goes
+    // with the base
+    pub segment: Option<Reg>,
+}
+
+impl EAC {
+    pub fn new(base: EABase, displacement: Option<i16>) -> Self {
+        Self {
+            base,
+            displacement,
+            segment: None,
+        }
+    }
+
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // the segment it's in without an override
+    pub fn default_segment(&self) -> Reg {
+        self.base.default_segment()
+    }
+
+    pub(crate) fn displacement_size(&self) -> usize {
+        match (self.base, self.displacement) {
+            (EABase::DirectAddr(_), _) => 2,
+            (_, None) => 0,
+            (_, Some(d)) if d == (d as i8) as i16 => 1,
+            (_, Some(_)) => 2,
+        }
+    }
+}
+
+// nasm wants the override inside the brackets
+impl fmt::Display for EAC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[")?;
+        if let Some(reg) = self.segment {
+            write!(f, "{}:", reg.asm())?;
+        }
+        match self.displacement {
+            None => write!(f, "{}]", self.base),
+            Some(d @ 0..) => write!(f, "{} + {}]", self.base, d),
+            Some(d) => write!(f, "{} - {}]", self.base, d.unsigned_abs()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EABase {
+    BxSi,
+    BxDi,
+    BpSi,
+    BpDi,
+    Si,
+    Di,
+    DirectAddr(u16),
+    Bx,
+    Bp,
+}
+
+impl EABase {
+    pub fn asm(&self) -> String {
+        self.to_string()
+    }
+
+    // bp based addresses are on the stack, everything else is data. the
+    // string ops' di is the exception, but that's not an address mode
+    pub fn default_segment(&self) -> Reg {
+        match self {
+            Self::Bp | Self::BpSi | Self::BpDi => Reg::SS,
+            _ => Reg::DS,
+        }
+    }
+}
+
+impl fmt::Display for EABase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BxSi => f.write_str("bx + si"),
+            Self::BxDi => f.write_str("bx + di"),
+            Self::BpSi => f.write_str("bp + si"),
+            Self::BpDi => f.write_str("bp + di"),
+            Self::Si => f.write_str("si"),
+            Self::Di => f.write_str("di"),
+            Self::Bx => f.write_str("bx"),
+            Self::Bp => f.write_str("bp"),
+            Self::DirectAddr(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RegIndex {
+    pub region: Region,
+    pub register: Reg,
+    pub mnemonic: &'static str, // only used for printing assembly, so lowercase
+}
+
+impl RegIndex {
+    pub const AL: RegIndex = RegIndex::new("al", Reg::A, Region::Low);
+    pub const AH: RegIndex = RegIndex::new("ah", Reg::A, Region::High);
+    pub const BL: RegIndex = RegIndex::new("bl", Reg::B, Region::Low);
+    pub const BH: RegIndex = RegIndex::new("bh", Reg::B, Region::High);
+    pub const CL: RegIndex = RegIndex::new("cl", Reg::C, Region::Low);
+    pub const CH: RegIndex = RegIndex::new("ch", Reg::C, Region::High);
+    pub const DL: RegIndex = RegIndex::new("dl", Reg::D, Region::Low);
+    pub const DH: RegIndex = RegIndex::new("dh", Reg::D, Region::High);
+    pub const AX: RegIndex = RegIndex::new("ax", Reg::A, Region::Xtended);
+    pub const BX: RegIndex = RegIndex::new("bx", Reg::B, Region::Xtended);
+    pub const CX: RegIndex = RegIndex::new("cx", Reg::C, Region::Xtended);
+    pub const DX: RegIndex = RegIndex::new("dx", Reg::D, Region::Xtended);
+    pub const SP: RegIndex = RegIndex::new("sp", Reg::SP, Region::Xtended);
+    pub const BP: RegIndex = RegIndex::new("bp", Reg::BP, Region::Xtended);
+    pub const SI: RegIndex = RegIndex::new("si", Reg::SI, Region::Xtended);
+    pub const DI: RegIndex = RegIndex::new("di", Reg::DI, Region::Xtended);
+    pub const IP: RegIndex = RegIndex::new("ip", Reg::IP, Region::Xtended);
+    pub const ES: RegIndex = RegIndex::new("es", Reg::ES, Region::Xtended);
+    pub const CS: RegIndex = RegIndex::new("cs", Reg::CS, Region::Xtended);
+    pub const SS: RegIndex = RegIndex::new("ss", Reg::SS, Region::Xtended);
+    pub const DS: RegIndex = RegIndex::new("ds", Reg::DS, Region::Xtended);
+
+    pub(crate) const ALL: [RegIndex; 21] = [
+        Self::AX,
+        Self::BX,
+        Self::CX,
+        Self::DX,
+        Self::SP,
+        Self::BP,
+        Self::SI,
+        Self::DI,
+        Self::ES,
+        Self::CS,
+        Self::SS,
+        Self::DS,
+        Self::IP,
+        Self::AL,
+        Self::AH,
+        Self::BL,
+        Self::BH,
+        Self::CL,
+        Self::CH,
+        Self::DL,
+        Self::DH,
+    ];
+
+    // `name` in any case, like "bx" or "AL". the other way from `asm`
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|reg| reg.mnemonic.eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub(crate) const fn new(mnemonic: &'static str, register: Reg, region: Region) -> Self {
+        Self {
+            mnemonic,
+            register,
+            region,
+        }
+    }
+
+    pub fn asm(&self) -> &str {
+        self.mnemonic
+    }
+
+    pub(crate) fn acc(w: bool) -> Self {
+        if w {
+            Self::AX
+        } else {
+            Self::AL
+        }
+    }
+
+    // AH never gets the accumulator short forms
+    pub(crate) fn is_acc(&self) -> bool {
+        matches!(self.register, Reg::A) && !matches!(self.region, Region::High)
+    }
+}
+
+impl fmt::Display for RegIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic)
+    }
+}
+
+// the mnemonic is just for printing, two indices are the same if they point
+// at the same bytes
+impl PartialEq for RegIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.register == other.register && self.region == other.region
+    }
+}
+
+impl Eq for RegIndex {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    Xtended, // 16 bits
+    Low,     // 8 bits
+    High,    // 8 bits
+}
+
+impl Region {
+    pub(crate) fn width(&self) -> Width {
+        match self {
+            Self::Xtended => Width::Word,
+            Self::Low | Self::High => Width::Byte,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+impl Width {
+    pub(crate) fn mask(&self) -> u16 {
+        match self {
+            Self::Byte => 0x00ff,
+            Self::Word => 0xffff,
+        }
+    }
+
+    pub(crate) fn keyword(&self) -> &'static str {
+        match self {
+            Self::Byte => "byte",
+            Self::Word => "word",
+        }
+    }
+
+    pub(crate) fn sign_bit(&self) -> u16 {
+        match self {
+            Self::Byte => 0x0080,
+            Self::Word => 0x8000,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+#![feature(variant_count)]
+// opcode bit patterns are grouped by instruction field, not by nibble
+#![allow(clippy::unusual_byte_groupings, clippy::upper_case_acronyms)]
+
+mod assemble;
+mod config;
+mod cpu;
+mod decode;
+mod disasm;
+mod estimate;
+mod instruction;
+mod render;
+mod stats;
+mod syntax;
+mod verify;
+
+pub use assemble::{assemble, AsmError};
+pub use config::{Config, ConfigError, Dump, Mode, Render, USAGE};
+pub use cpu::{
+    flags_from_string, flags_from_word, flags_to_word, format_flags, physical_address, CpuState,
+    Exec, ExecError, Flag, Flags, MemAccess, RegWrite, CPU, MEMORY_SIZE, SEGMENTED_MEMORY_SIZE,
+};
+pub use decode::{decode, decode_first_at, DecodeCache, DecodeError, Decoder, ReadDecoder};
+pub use disasm::{disassemble, jump_target};
+pub use estimate::{
+    estimate_8086, estimate_8086_exec, estimate_8086_parts, estimate_8088, estimate_8088_exec,
+    transfer_penalty_8088, unaligned_penalty_8086,
+};
+pub use instruction::*;
+pub use render::ppm;
+pub use stats::{Branch, Stats};
+pub use syntax::Syntax;
+pub use verify::{verify, Mismatch};
@@ -0,0 +1,15 @@
+// a binary PPM of `width` x `height` pixels from `memory[start..]`. the
+// course's pixels are 4 bytes each, RGBA, a row at a time with nothing
+// between rows. PPM has no alpha, so that's dropped, and any pixel that
+// would be past the end of memory comes out black
+pub fn ppm(memory: &[u8], start: usize, width: usize, height: usize) -> Vec<u8> {
+    let mut image = format!("P6\n{width} {height}\n255\n").into_bytes();
+    for pixel in 0..width * height {
+        let addr = start.saturating_add(pixel * 4);
+        let rgb = memory
+            .get(addr..addr.saturating_add(3))
+            .unwrap_or(&[0, 0, 0]);
+        image.extend_from_slice(rgb);
+    }
+    image
+}
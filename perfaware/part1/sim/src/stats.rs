@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::cpu::CPU;
+use crate::instruction::{Instruction, Op};
+
+// what a run did, for --stats
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub executed: usize,
+    pub by_mnemonic: HashMap<&'static str, usize>,
+    pub taken: usize,
+    pub not_taken: usize,
+    // each conditional jump on its own, by the ip it's at
+    pub by_ip: HashMap<u16, Branch>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub evaluated: usize,
+    pub taken: usize,
+}
+
+impl Stats {
+    // call before exec, since whether a jump is taken depends on the state
+    // it starts from
+    pub fn record(&mut self, inst: &Instruction, cpu: &CPU) {
+        self.executed += 1;
+        *self.by_mnemonic.entry(inst.mnemonic()).or_default() += 1;
+        if let Op::Jump(jump) = &inst.op {
+            let is_taken = cpu.is_jump_taken(jump);
+            if is_taken {
+                self.taken += 1;
+            } else {
+                self.not_taken += 1;
+            }
+            let branch = self.by_ip.entry(cpu.ip()).or_default();
+            branch.evaluated += 1;
+            branch.taken += is_taken as usize;
+        }
+    }
+
+    pub fn count(&self, mnemonic: &str) -> usize {
+        self.by_mnemonic.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    // most executed first, ties in alphabetical order
+    pub fn histogram(&self) -> Vec<(&str, usize)> {
+        let mut counts = self
+            .by_mnemonic
+            .iter()
+            .map(|(mnemonic, count)| (*mnemonic, *count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+
+    // in program order
+    pub fn branches(&self) -> Vec<(u16, Branch)> {
+        let mut branches = self
+            .by_ip
+            .iter()
+            .map(|(ip, branch)| (*ip, *branch))
+            .collect::<Vec<_>>();
+        branches.sort_by_key(|(ip, _)| *ip);
+        branches
+    }
+}
@@ -0,0 +1,189 @@
+use crate::instruction::*;
+
+// which assembler the disassembly is written for. `Instruction::asm` is
+// always intel, the way nasm takes it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Intel,
+    // the GNU as flavour, to compare against objdump
+    Att,
+}
+
+impl Syntax {
+    pub fn asm(&self, inst: &Instruction) -> String {
+        match self {
+            Self::Intel => inst.asm(),
+            Self::Att => att_asm(inst),
+        }
+    }
+
+    // the same, for writing straight to the output with no `String` in
+    // between. intel goes all the way through `Display`
+    pub fn display<'a>(&self, inst: &'a Instruction) -> impl std::fmt::Display + 'a {
+        let syntax = *self;
+        std::fmt::from_fn(move |f| match syntax {
+            Self::Intel => write!(f, "{inst}"),
+            Self::Att => f.write_str(&att_asm(inst)),
+        })
+    }
+
+    // what starts a comment, for anything written alongside the code
+    pub fn comment_prefix(&self) -> &'static str {
+        match self {
+            Self::Intel => ";",
+            Self::Att => "#",
+        }
+    }
+}
+
+// source first, sigils on registers and immediates, and the operand size on
+// the mnemonic instead of a keyword on the memory operand
+fn att_asm(inst: &Instruction) -> String {
+    match &inst.op {
+        Op::Mov(Mov { dst, src }) => binop("mov", *dst, *src),
+        Op::Add(Add { dst, src }) => binop("add", *dst, *src),
+        Op::Adc(Adc { dst, src }) => binop("adc", *dst, *src),
+        Op::Sub(Sub { dst, src }) => binop("sub", *dst, *src),
+        Op::Sbb(Sbb { dst, src }) => binop("sbb", *dst, *src),
+        Op::Cmp(Cmp { dst, src }) => binop("cmp", *dst, *src),
+        Op::And(And { dst, src }) => binop("and", *dst, *src),
+        Op::Or(Or { dst, src }) => binop("or", *dst, *src),
+        Op::Xor(Xor { dst, src }) => binop("xor", *dst, *src),
+        Op::Test(Test { dst, src }) => binop("test", *dst, *src),
+        Op::Xchg(Xchg { dst, src }) => binop("xchg", *dst, *src),
+        Op::Inc(Inc { dst, width }) => unary("inc", *dst, *width),
+        Op::Dec(Dec { dst, width }) => unary("dec", *dst, *width),
+        Op::Push(Push { src }) => unary("push", *src, Width::Word),
+        Op::Pop(Pop { dst }) => unary("pop", *dst, Width::Word),
+        Op::Mul(Mul { src, width, signed }) => {
+            unary(if *signed { "imul" } else { "mul" }, *src, *width)
+        }
+        Op::Div(Div { src, width, signed }) => {
+            unary(if *signed { "idiv" } else { "div" }, *src, *width)
+        }
+        // shifting by 1 has no count, the way objdump prints it
+        Op::Shift(shift) if shift.by_cl => format!(
+            "{}{} %cl, {}",
+            shift.op.asm(),
+            suffix(shift.width),
+            loc_asm(shift.dst)
+        ),
+        Op::Shift(shift) => unary(shift.op.asm(), shift.dst, shift.width),
+        Op::Jump(jump) => {
+            format!(
+                "{} {}",
+                jump.typ.asm(),
+                relative(jump.size() as i32 + jump.offset as i32)
+            )
+        }
+        Op::Call(call) => {
+            format!("call {}", relative(call.size() as i32 + call.offset as i32))
+        }
+        Op::CallIndirect(indirect) => indirect_asm("call", indirect),
+        Op::JmpIndirect(indirect) => indirect_asm("jmp", indirect),
+        Op::Ret(Ret { pop: Some(n) }) => format!("ret ${n}"),
+        Op::In(In { port, width }) => {
+            format!("in{} {}, {}", suffix(*width), port_asm(*port), acc(*width))
+        }
+        Op::Out(Out { port, width }) => {
+            format!("out{} {}, {}", suffix(*width), acc(*width), port_asm(*port))
+        }
+        Op::Adjust(Adjust::Aam(base)) if *base != 10 => format!("aam ${base}"),
+        Op::Adjust(Adjust::Aad(base)) if *base != 10 => format!("aad ${base}"),
+        Op::Unknown(b) => format!(".byte {:#04x}", b),
+        Op::Esc(esc) => format!("esc ${}, {}", esc.opcode, loc_asm(esc.src)),
+        // nothing to reorder
+        Op::Ret(_)
+        | Op::Pushf
+        | Op::Popf
+        | Op::Cld
+        | Op::Std
+        | Op::Str(_)
+        | Op::Hlt
+        | Op::Wait
+        | Op::Adjust(_) => inst.asm(),
+    }
+}
+
+fn binop(mnemonic: &str, dst: Loc, src: Loc) -> String {
+    let width = Loc::op_width(dst, src);
+    format!(
+        "{mnemonic}{} {}, {}",
+        suffix(width),
+        loc_asm(src),
+        loc_asm(dst)
+    )
+}
+
+fn unary(mnemonic: &str, loc: Loc, width: Width) -> String {
+    format!("{mnemonic}{} {}", suffix(width), loc_asm(loc))
+}
+
+// `*` marks the operand as where to go, rather than the address of it
+fn indirect_asm(mnemonic: &str, indirect: &Indirect) -> String {
+    let far = if indirect.far { "l" } else { "" };
+    format!("{far}{mnemonic} *{}", loc_asm(indirect.target))
+}
+
+// gas's `.` is nasm's `$`, the start of the instruction
+fn relative(offset: i32) -> String {
+    format!(".{offset:+}")
+}
+
+fn suffix(width: Width) -> &'static str {
+    match width {
+        Width::Byte => "b",
+        Width::Word => "w",
+    }
+}
+
+fn acc(width: Width) -> &'static str {
+    match width {
+        Width::Byte => "%al",
+        Width::Word => "%ax",
+    }
+}
+
+fn port_asm(port: Port) -> String {
+    match port {
+        Port::Fixed(n) => format!("${n}"),
+        Port::Dx => "(%dx)".into(),
+    }
+}
+
+fn loc_asm(loc: Loc) -> String {
+    match loc {
+        Loc::Reg(reg) => format!("%{}", reg.asm()),
+        Loc::Imm8(n) => format!("${n}"),
+        Loc::Imm16(n) => format!("${n}"),
+        Loc::EAC(eac) => eac_asm(eac),
+    }
+}
+
+// `segment:displacement(base,index)`, and a bare number is a direct address
+fn eac_asm(eac: EAC) -> String {
+    let segment = match eac.segment {
+        Some(reg) => format!("%{}:", reg.asm()),
+        None => String::new(),
+    };
+    format!("{segment}{}", eac_offset_asm(eac))
+}
+
+fn eac_offset_asm(eac: EAC) -> String {
+    let regs = match eac.base {
+        EABase::BxSi => "(%bx,%si)",
+        EABase::BxDi => "(%bx,%di)",
+        EABase::BpSi => "(%bp,%si)",
+        EABase::BpDi => "(%bp,%di)",
+        EABase::Si => "(%si)",
+        EABase::Di => "(%di)",
+        EABase::Bx => "(%bx)",
+        EABase::Bp => "(%bp)",
+        EABase::DirectAddr(n) => return n.to_string(),
+    };
+    match eac.displacement {
+        Some(d) => format!("{d}{regs}"),
+        None => regs.into(),
+    }
+}
@@ -0,0 +1,60 @@
+use crate::config::parse_number;
+use crate::cpu::{flags_from_string, format_flags, CPU};
+use crate::instruction::{Loc, RegIndex};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub name: String,
+    pub want: String,
+    pub got: String,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: expected {}, got {}", self.name, self.want, self.got)
+    }
+}
+
+// `expected` is in the same format as the final dump, like the course's
+// reference output. only the registers it lists are checked (the references
+// leave out the ones that are 0), and any line that isn't a register or the
+// flags is skipped, so a whole reference file works as is
+pub fn verify(expected: &str, cpu: &CPU) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    for line in expected.lines() {
+        let Some((name, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+
+        if name == "flags" {
+            // in any order
+            if flags_from_string(value) != Some(cpu.flags()) {
+                mismatches.push(Mismatch {
+                    name: name.into(),
+                    want: value.into(),
+                    got: format_flags(&cpu.flags()),
+                });
+            }
+            continue;
+        }
+
+        let Some(reg) = RegIndex::from_mnemonic(name) else {
+            continue;
+        };
+        let want = value
+            .split_whitespace()
+            .next()
+            .and_then(parse_number)
+            .unwrap_or(usize::MAX);
+        let got = cpu.get_src(Loc::Reg(reg));
+        if want != got as usize {
+            mismatches.push(Mismatch {
+                name: name.into(),
+                want: value.into(),
+                got: format!("{:#06x} ({})", got, got),
+            });
+        }
+    }
+    mismatches
+}
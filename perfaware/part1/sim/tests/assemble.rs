@@ -0,0 +1,277 @@
+use sim::{
+    assemble, decode, Adc, Add, And, AsmError, Cmp, EABase, Instruction, Jump, JumpType, Loc, Mov,
+    Op, Or, Reg, RegIndex, Sbb, Sub, Width, Xor, EAC,
+};
+
+fn read_listing(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn disassemble(bytes: &[u8]) -> String {
+    decode(bytes)
+        .unwrap()
+        .iter()
+        .map(|inst| inst.asm() + "\n")
+        .collect()
+}
+
+#[test]
+fn assembles_back_to_the_decoded_instruction() {
+    let bytes = assemble("mov ax, bx").unwrap();
+    assert_eq!(bytes, [0x89, 0xd8]);
+    assert_eq!(
+        decode(&bytes).unwrap(),
+        [Instruction::from(Op::Mov(Mov {
+            src: Loc::Reg(RegIndex::BX),
+            dst: Loc::Reg(RegIndex::AX),
+        }))]
+    );
+}
+
+#[test]
+fn disassembly_assembles_to_the_same_bytes() {
+    for name in [
+        "listing_0037_single_register_mov",
+        "listing_0038_many_register_mov",
+        "listing_0039_more_movs",
+        "listing_0040_challenge_movs",
+        "listing_0041_add_sub_cmp_jnz",
+    ] {
+        let want = read_listing(name);
+        assert_eq!(assemble(&disassemble(&want)).unwrap(), want, "{name}");
+    }
+}
+
+#[test]
+fn picks_the_shortest_encoding() {
+    let asm = "\
+bits 16
+mov cl, 12
+mov word [bx + 2], 1000
+mov ax, [1000]
+mov [es:bp + 0], al
+mov ds, bx
+add si, 2 ; sign extended from a byte
+add ax, 1000
+add al, -30
+cmp byte [bp - 300], 7
+jnz $-4
+";
+    let bytes = assemble(asm).unwrap();
+    assert_eq!(
+        bytes,
+        [
+            0xb1, 0x0c, // mov cl, 12
+            0xc7, 0x47, 0x02, 0xe8, 0x03, // mov word [bx + 2], 1000
+            0xa1, 0xe8, 0x03, // mov ax, [1000]
+            0x26, 0x88, 0x46, 0x00, // mov [es:bp + 0], al
+            0x8e, 0xdb, // mov ds, bx
+            0x83, 0xc6, 0x02, // add si, 2
+            0x05, 0xe8, 0x03, // add ax, 1000
+            0x04, 0xe2, // add al, -30
+            0x80, 0xbe, 0xd4, 0xfe, 0x07, // cmp byte [bp - 300], 7
+            0x75, 0xfa, // jnz $-4
+        ]
+    );
+}
+
+#[test]
+fn reports_the_line_that_went_wrong() {
+    assert_eq!(
+        assemble("mov ax, bx\nhlt"),
+        Err(AsmError::UnknownMnemonic {
+            line: 2,
+            mnemonic: "hlt".into()
+        })
+    );
+    for bad in [
+        "mov [bx], 5",
+        "mov ax, bl",
+        "mov [bx], [si]",
+        "add es, ax",
+        "mov ax, [bl]",
+    ] {
+        assert_eq!(
+            assemble(bad),
+            Err(AsmError::BadOperands {
+                line: 1,
+                text: bad.into()
+            })
+        );
+    }
+    for bad in ["mov al, 256", "jnz $+200"] {
+        assert_eq!(
+            assemble(bad),
+            Err(AsmError::OutOfRange {
+                line: 1,
+                text: bad.into()
+            })
+        );
+    }
+}
+
+// xorshift, so the instructions are the same every run without a crate for it
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+const BYTE_REGS: [RegIndex; 8] = [
+    RegIndex::AL,
+    RegIndex::CL,
+    RegIndex::DL,
+    RegIndex::BL,
+    RegIndex::AH,
+    RegIndex::CH,
+    RegIndex::DH,
+    RegIndex::BH,
+];
+const WORD_REGS: [RegIndex; 8] = [
+    RegIndex::AX,
+    RegIndex::CX,
+    RegIndex::DX,
+    RegIndex::BX,
+    RegIndex::SP,
+    RegIndex::BP,
+    RegIndex::SI,
+    RegIndex::DI,
+];
+const SEGMENT_REGS: [RegIndex; 4] = [RegIndex::ES, RegIndex::CS, RegIndex::SS, RegIndex::DS];
+
+fn random_eac(rng: &mut Rng) -> EAC {
+    let segment = rng.pick(&[
+        None,
+        Some(Reg::ES),
+        Some(Reg::CS),
+        Some(Reg::SS),
+        Some(Reg::DS),
+    ]);
+    let base = match rng.below(9) {
+        0 => EABase::BxSi,
+        1 => EABase::BxDi,
+        2 => EABase::BpSi,
+        3 => EABase::BpDi,
+        4 => EABase::Si,
+        5 => EABase::Di,
+        6 => EABase::Bx,
+        7 => EABase::Bp,
+        _ => {
+            return EAC {
+                base: EABase::DirectAddr(rng.next() as u16),
+                displacement: None,
+                segment,
+            }
+        }
+    };
+    // one byte, two bytes, or none (which [bp] can't have)
+    let displacement = match rng.below(3) {
+        0 => Some(rng.next() as i8 as i16),
+        1 => Some(rng.next() as i16),
+        _ if base == EABase::Bp => Some(0),
+        _ => None,
+    };
+    EAC {
+        base,
+        displacement,
+        segment,
+    }
+}
+
+fn random_operands(rng: &mut Rng, is_mov: bool) -> (Loc, Loc) {
+    let width = rng.pick(&[Width::Byte, Width::Word]);
+    let (regs, imm) = match width {
+        Width::Byte => (BYTE_REGS, Loc::Imm8(rng.next() as u8)),
+        Width::Word => (WORD_REGS, Loc::Imm16(rng.next() as u16)),
+    };
+    let reg = Loc::Reg(rng.pick(&regs));
+    let mem = Loc::EAC(random_eac(rng));
+    let sreg = Loc::Reg(rng.pick(&SEGMENT_REGS));
+    let word_reg = Loc::Reg(rng.pick(&WORD_REGS));
+    let word_r_m = rng.pick(&[word_reg, mem]);
+    match rng.below(if is_mov { 7 } else { 5 }) {
+        0 => (reg, Loc::Reg(rng.pick(&regs))),
+        1 => (reg, mem),
+        2 => (mem, reg),
+        3 => (reg, imm),
+        4 => (mem, imm),
+        // segment registers go to and from words
+        5 => (sreg, word_r_m),
+        _ => (word_r_m, sreg),
+    }
+}
+
+fn random_op(rng: &mut Rng) -> Op {
+    if rng.below(10) == 0 {
+        return Op::Jump(Jump {
+            typ: rng.pick(&JUMPS),
+            offset: rng.next() as i8,
+        });
+    }
+    let op = rng.below(9);
+    let (dst, src) = random_operands(rng, op == 8);
+    match op {
+        0 => Op::Add(Add { dst, src }),
+        1 => Op::Or(Or { dst, src }),
+        2 => Op::Adc(Adc { dst, src }),
+        3 => Op::Sbb(Sbb { dst, src }),
+        4 => Op::And(And { dst, src }),
+        5 => Op::Sub(Sub { dst, src }),
+        6 => Op::Xor(Xor { dst, src }),
+        7 => Op::Cmp(Cmp { dst, src }),
+        _ => Op::Mov(Mov { dst, src }),
+    }
+}
+
+const JUMPS: [JumpType; 20] = [
+    JumpType::Jnz,
+    JumpType::Je,
+    JumpType::Jl,
+    JumpType::Jle,
+    JumpType::Jb,
+    JumpType::Jbe,
+    JumpType::Jp,
+    JumpType::Jo,
+    JumpType::Js,
+    JumpType::Jnl,
+    JumpType::Jg,
+    JumpType::Jnb,
+    JumpType::Ja,
+    JumpType::Jnp,
+    JumpType::Jno,
+    JumpType::Jns,
+    JumpType::Loop,
+    JumpType::Loopz,
+    JumpType::Loopnz,
+    JumpType::Jcxz,
+];
+
+#[test]
+fn random_instructions_survive_assembling_and_decoding() {
+    let mut rng = Rng(0x8086_8088);
+    for _ in 0..10_000 {
+        let want = Instruction::from(random_op(&mut rng));
+        let asm = want.asm();
+        let bytes = assemble(&asm).unwrap_or_else(|err| panic!("{asm}: {err}"));
+        let got = decode(&bytes);
+        assert_eq!(
+            got.as_deref(),
+            Ok(std::slice::from_ref(&want)),
+            "{asm} assembled to {bytes:02x?}"
+        );
+        assert_eq!(bytes.len(), want.size(), "{asm} assembled to {bytes:02x?}");
+    }
+}
@@ -0,0 +1,634 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn run_with_stdin(args: &[&str], program: &[u8]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(program).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn reads_the_program_from_stdin() {
+    for args in [&["-"][..], &[]] {
+        // mov cx, bx
+        let stdout = run_with_stdin(args, &[0x89, 0xd9]);
+        assert_eq!(stdout, "bits 16\nmov cx, bx\n");
+    }
+}
+
+#[test]
+fn flags_work_with_stdin() {
+    // mov cx, 3
+    let stdout = run_with_stdin(&["-", "-exec"], &[0xb9, 0x03, 0x00]);
+    assert!(stdout.contains("cx: 0x0003 (3)"), "{stdout}");
+}
+
+#[test]
+fn decodes_several_files_in_one_go() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    let names = [
+        "listing_0037_single_register_mov",
+        "listing_0038_many_register_mov",
+    ];
+    let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(names.map(|name| format!("{dir}/{name}")))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let headers = stdout
+        .lines()
+        .filter(|line| line.starts_with("; ===="))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        headers,
+        names.map(|name| format!("; ==== {dir}/{name} ===="))
+    );
+    assert_eq!(stdout.matches("bits 16").count(), 2);
+}
+
+#[test]
+fn counts_instructions_without_printing_them() {
+    let listing = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../listing_0041_add_sub_cmp_jnz"
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(["--count", listing])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // one per line of the source, less the labels
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "96 instructions, 247 bytes\n"
+    );
+}
+
+#[test]
+fn origin_moves_ip() {
+    // mov cx, 3
+    let stdout = run_with_stdin(&["--exec", "--origin", "0x100"], &[0xb9, 0x03, 0x00]);
+    assert!(stdout.contains("ip: 0x0103 (259)"), "{stdout}");
+}
+
+#[test]
+fn prints_byte_registers() {
+    // mov al, 0x12
+    let stdout = run_with_stdin(&["--exec", "--byte-registers"], &[0xb0, 0x12]);
+    assert!(stdout.contains("      al: 0x12 (18)\n"), "{stdout}");
+    assert!(stdout.contains("      ah: 0x00 (0)\n"), "{stdout}");
+
+    let stdout = run_with_stdin(&["--exec"], &[0xb0, 0x12]);
+    assert!(!stdout.contains("al:"), "{stdout}");
+}
+
+#[test]
+fn traces_memory_addresses() {
+    let stdout = run_with_stdin(
+        &["--exec", "--trace-addresses"],
+        &[
+            0xbb, 0xe8, 0x03, // mov bx, 1000
+            0x83, 0x07, 0x01, // add word [bx], 1
+        ],
+    );
+    let line = stdout.lines().nth(1).unwrap();
+    assert!(line.ends_with("read:0x003e8 write:0x003e8 "), "{line}");
+}
+
+// the output is written without going through `Instruction::asm`, this
+// checks it's still the same text. no jumps, those get a comment too
+#[test]
+fn decoding_prints_the_same_as_asm() {
+    let bytes = ["listing_0040_challenge_movs", "listing_0046_add_sub_cmp"]
+        .map(|name| std::fs::read(format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap())
+        .concat();
+    let insts = sim::decode(&bytes).unwrap();
+    for syntax in [sim::Syntax::Intel, sim::Syntax::Att] {
+        let mut want = String::from("bits 16\n");
+        for inst in &insts {
+            want += &syntax.asm(inst);
+            want += "\n";
+        }
+        let args: &[&str] = match syntax {
+            sim::Syntax::Intel => &[],
+            sim::Syntax::Att => &["--syntax", "att"],
+        };
+        assert_eq!(run_with_stdin(args, &bytes), want, "{syntax:?}");
+    }
+}
+
+#[test]
+fn att_annotations_are_gas_comments() {
+    let stdout = run_with_stdin(
+        &["--syntax", "att", "--addresses", "--cycle-estimate"],
+        &[
+            0x50, // push ax
+            0x75, 0x10, // jnz past the end
+            0xf3, 0xa4, // rep movsb
+        ],
+    );
+    assert_eq!(
+        stdout,
+        "bits 16\n\
+         pushw %ax # @0x0000 # Clocks: +11 = 11\n\
+         jnz .+18 # @0x0001 # -> outside the program # Clocks: +16 = 27\n\
+         rep movsb # @0x0003 # Clocks: ?\n\
+         \n\
+         Total cycles: 27\n"
+    );
+    assert!(!stdout.contains(';'), "{stdout}");
+}
+
+#[test]
+fn cycle_estimate_breaks_out_the_ea() {
+    let stdout = run_with_stdin(
+        &["--cycle-estimate-8088"],
+        &[
+            0x8b, 0x16, 0xe8, 0x03, // mov dx, [1000]
+            0x89, 0xd9, // mov cx, bx
+        ],
+    );
+    assert_eq!(
+        stdout,
+        "bits 16\nmov dx, [1000] ; Clocks: +18 = 18 (8 + 6ea + 4p)\n\
+         mov cx, bx ; Clocks: +2 = 20\n\nTotal cycles: 20\n"
+    );
+}
+
+#[test]
+fn cycle_estimate_marks_what_it_cant_count() {
+    let stdout = run_with_stdin(
+        &["--cycle-estimate"],
+        &[
+            0x50, // push ax
+            0x31, 0xc0, // xor ax, ax
+            0xf3, 0xa4, // rep movsb
+            0xc3, // ret
+        ],
+    );
+    assert_eq!(
+        stdout,
+        "bits 16\npush ax ; Clocks: +11 = 11\nxor ax, ax ; Clocks: +3 = 14\n\
+         rep movsb ; Clocks: ?\nret ; Clocks: +8 = 22\n\nTotal cycles: 22\n"
+    );
+}
+
+#[test]
+fn writes_the_disassembly_to_a_file() {
+    let file = std::env::temp_dir().join(format!("sim-output-{}.asm", std::process::id()));
+    let stdout = run_with_stdin(
+        &["-o", file.to_str().unwrap(), "--cycle-estimate"],
+        &[0x89, 0xd9], // mov cx, bx
+    );
+    assert_eq!(stdout, "");
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "bits 16\nmov cx, bx ; Clocks: +2 = 2\n\nTotal cycles: 2\n"
+    );
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn dumps_a_memory_range() {
+    let file = std::env::temp_dir().join(format!("sim-dump-{}.bin", std::process::id()));
+    let dump = format!("1000:4:{}", file.display());
+    run_with_stdin(
+        &["--exec", "--dump", &dump],
+        &[
+            0xc7, 0x06, 0xe9, 0x03, 0x34, 0x12, // mov word [1001], 0x1234
+        ],
+    );
+    assert_eq!(std::fs::read(&file).unwrap(), [0x00, 0x34, 0x12, 0x00]);
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn renders_pixels_to_a_ppm() {
+    let file = std::env::temp_dir().join(format!("sim-render-{}.ppm", std::process::id()));
+    let render = format!("2x1:0x100:{}", file.display());
+    run_with_stdin(
+        &["--exec", "--render", &render],
+        &[
+            0xc7, 0x06, 0x00, 0x01, 0xff, 0x00, // mov word [0x100], 0x00ff
+            0xc7, 0x06, 0x04, 0x01, 0x10, 0x20, // mov word [0x104], 0x2010
+            0xc6, 0x06, 0x07, 0x01, 0xff, // mov byte [0x107], 255
+        ],
+    );
+    let mut expected = b"P6\n2 1\n255\n".to_vec();
+    expected.extend([0xff, 0x00, 0x00, 0x10, 0x20, 0x00]);
+    assert_eq!(std::fs::read(&file).unwrap(), expected);
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn set_puts_the_stack_somewhere_else() {
+    let file = std::env::temp_dir().join(format!("sim-stack-{}.bin", std::process::id()));
+    let dump = format!("0x1fffc:2:{}", file.display());
+    let stdout = run_with_stdin(
+        &[
+            "--exec",
+            "--segmented",
+            "--set",
+            "sp=0xfffe",
+            "--set",
+            "ss=0x1000",
+            "--dump",
+            &dump,
+        ],
+        &[
+            0xb8, 0x34, 0x12, // mov ax, 0x1234
+            0x50, // push ax
+        ],
+    );
+    // 0x1000 * 16 + 0xfffc
+    assert_eq!(std::fs::read(&file).unwrap(), [0x34, 0x12]);
+    assert!(stdout.contains("sp: 0xfffc"), "{stdout}");
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn jumps_show_where_they_go() {
+    let path = format!(
+        "{}/../listing_0050_challenge_jumps",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .arg(path)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let targets = stdout
+        .lines()
+        .filter_map(|line| line.split_once(" ; -> "))
+        .map(|(_, target)| target)
+        .collect::<Vec<_>>();
+    assert_eq!(targets, ["0x12", "0x17", "0x1a", "0x9"]);
+
+    // jnz back past the start
+    let stdout = run_with_stdin(&[], &[0x75, 0x80]);
+    assert!(stdout.contains("; -> outside the program"), "{stdout}");
+}
+
+#[test]
+fn verifies_the_final_state() {
+    let file = std::env::temp_dir().join(format!("sim-verify-{}.txt", std::process::id()));
+    // mov cx, 3; sub cx, 3
+    let program = [0xb9, 0x03, 0x00, 0x83, 0xe9, 0x03];
+    let verify = |expected: &str| {
+        std::fs::write(&file, expected).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+            .args(["--exec", "--verify", file.to_str().unwrap()])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(&program).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = verify("Final registers:\n      ip: 0x0006 (6)\n   flags: ZP\n");
+    assert!(output.status.success());
+
+    let output = verify("      cx: 0x0003 (3)\n      ip: 0x0006 (6)\n   flags: S\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "mismatch: cx: expected 0x0003 (3), got 0x0000 (0)\n\
+         mismatch: flags: expected S, got PZ\n"
+    );
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn unreadable_files_are_an_error() {
+    let missing = std::env::temp_dir().join(format!("sim-missing-{}", std::process::id()));
+    let dir = env!("CARGO_MANIFEST_DIR");
+    // a directory opens, it's the read that fails
+    for path in [missing.to_str().unwrap(), dir] {
+        for args in [&[][..], &["--exec"]] {
+            let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+                .args(args)
+                .arg(path)
+                .output()
+                .unwrap();
+            assert!(!output.status.success());
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            assert!(stderr.starts_with(&format!("{path}: ")), "{stderr}");
+            assert!(!stderr.contains("panicked"), "{stderr}");
+        }
+    }
+}
+
+#[test]
+fn dumping_somewhere_unwritable_is_an_error() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(["--exec", "--dump", "0:4:/nonexistent/dump.bin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&[0x90]).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("error: can't write /nonexistent/dump.bin: "),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn rendering_somewhere_unwritable_is_an_error() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(["--exec", "--render", "2x2:0:/nonexistent/image.ppm"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&[0x90]).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("error: can't write /nonexistent/image.ppm: "),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn decode_errors_show_where_they_are() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // mov cx, bx twice, with a byte that isn't an instruction between
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&[0x89, 0xd9, 0x89, 0xd9, 0x0f, 0x89, 0xd9])
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "error: unknown opcode 0b00001111 at offset 0x4 (context: 89 D9 89 D9 [0F] 89 D9)\n"
+    );
+}
+
+#[test]
+fn max_instructions_stops_an_endless_loop() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(["--exec", "--max-instructions", "100"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // jnz $, and zf starts clear
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&[0x75, 0xfe])
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "error at ip 0x0000: stopped after 100 instructions\n"
+    );
+
+    // a program that finishes in time isn't affected
+    let stdout = run_with_stdin(&["--exec", "--max-instructions", "1"], &[0xb9, 0x03, 0x00]);
+    assert!(stdout.contains("cx: 0x0003 (3)"), "{stdout}");
+}
+
+#[test]
+fn breakpoints_dump_the_registers() {
+    let program = [
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0x83, 0xe9, 0x01, // sub cx, 1
+        0x75, 0xfb, // jnz back to the sub
+    ];
+    let stdout = run_with_stdin(&["--exec", "--break", "3"], &program);
+    let hits = stdout
+        .lines()
+        .filter(|line| *line == "Breakpoint at ip 0x0003:")
+        .count();
+    assert_eq!(hits, 3, "{stdout}");
+    assert!(stdout.contains("      cx: 0x0002 (2)\n"), "{stdout}");
+
+    let stdout = run_with_stdin(&["--exec", "--break", "3", "--break-stop"], &program);
+    assert_eq!(stdout.matches("Breakpoint").count(), 1, "{stdout}");
+    let end = stdout.split_once("Final registers:").unwrap().1;
+    assert!(end.contains("      cx: 0x0003 (3)\n"), "{stdout}");
+    assert!(end.contains("      ip: 0x0003 (3)\n"), "{stdout}");
+}
+
+#[test]
+fn steps_one_instruction_at_a_time() {
+    let file = std::env::temp_dir().join(format!("sim-step-{}.bin", std::process::id()));
+    std::fs::write(
+        &file,
+        [
+            0xb9, 0x03, 0x00, // mov cx, 3
+            0xbb, 0x04, 0x00, // mov bx, 4
+            0xba, 0x05, 0x00, // mov dx, 5
+        ],
+    )
+    .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args(["--exec", "--step", file.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // run the first, look at the registers, run the second, then quit
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"\nr\n\nq\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // what's typed isn't echoed, so the prompts run into whatever follows
+    let mut prompts = stdout
+        .split(" > ")
+        .map(|part| part.rsplit('\n').next().unwrap())
+        .collect::<Vec<_>>();
+    prompts.pop();
+    assert_eq!(
+        prompts,
+        [
+            "0x0000: mov cx, 3",
+            "0x0003: mov bx, 4",
+            "0x0003: mov bx, 4",
+            "0x0006: mov dx, 5",
+        ]
+    );
+    let end = stdout.split_once("Final registers:").unwrap().1;
+    assert!(end.contains("      bx: 0x0004 (4)\n"), "{stdout}");
+    assert!(end.contains("      dx: 0x0000 (0)\n"), "{stdout}");
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn listing_shows_addresses_and_bytes() {
+    let stdout = run_with_stdin(
+        &["--listing"],
+        &[
+            0x89, 0xd8, // mov ax, bx
+            0xc7, 0x87, 0xe8, 0x03, 0x05, 0x00, // mov word [bx + 1000], 5
+            0x75, 0xf6, // jnz back to the start
+        ],
+    );
+    assert_eq!(
+        stdout,
+        "0000: 89 D8              mov ax, bx\n\
+         0002: C7 87 E8 03 05 00  mov word [bx + 1000], 5\n\
+         0008: 75 F6              jnz $-8 ; -> 0x0\n"
+    );
+}
+
+#[test]
+fn addresses_go_up_by_each_instruction() {
+    let stdout = run_with_stdin(
+        &["--addresses", "--origin", "0x100"],
+        &[
+            0x89, 0xd8, // mov ax, bx
+            0xc7, 0x87, 0xe8, 0x03, 0x05, 0x00, // mov word [bx + 1000], 5
+        ],
+    );
+    assert_eq!(
+        stdout,
+        "bits 16\n\
+         org 0x100\n\
+         mov ax, bx ; @0x0100\n\
+         mov word [bx + 1000], 5 ; @0x0102\n"
+    );
+
+    let listing = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../listing_0041_add_sub_cmp_jnz"
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+        .args([listing, "--addresses"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let addrs = stdout
+        .lines()
+        .filter_map(|line| line.split_once("; @0x"))
+        .map(|(_, addr)| usize::from_str_radix(&addr[..4], 16).unwrap())
+        .collect::<Vec<_>>();
+    let bytes = std::fs::read(listing).unwrap();
+    let sizes = sim::decode(&bytes)
+        .unwrap()
+        .iter()
+        .map(|inst| inst.size())
+        .collect::<Vec<_>>();
+    assert_eq!(addrs.len(), sizes.len());
+    for (pair, size) in addrs.windows(2).zip(sizes) {
+        assert_eq!(pair[1] - pair[0], size, "after {:#x}", pair[0]);
+    }
+}
+
+#[test]
+fn range_decodes_part_of_the_file() {
+    let listing = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../listing_0041_add_sub_cmp_jnz"
+    );
+    let decode = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+            .arg(listing)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let full = decode(&["--listing"]);
+    let lines = full.lines().collect::<Vec<_>>();
+    // from the start of one line to the start of another
+    let addr = |line: &str| usize::from_str_radix(&line[..4], 16).unwrap();
+    let (start, end) = (addr(lines[10]), addr(lines[20]));
+    let part = decode(&["--listing", "--range", &format!("{start}:{end}")]);
+    assert_eq!(part.lines().collect::<Vec<_>>(), lines[10..20]);
+
+    // labels only go on jumps within the range
+    let part = decode(&["--labels", "--range", &format!("{start}:{end}")]);
+    let bytes = std::fs::read(listing).unwrap();
+    let expected = sim::disassemble(&bytes[start..end], false, sim::Syntax::Intel).unwrap();
+    assert_eq!(part.lines().skip(1).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn skip_unknown_prints_data_as_db() {
+    let stdout = run_with_stdin(
+        &["--skip-unknown"],
+        &[
+            0x89, 0xd9, // mov cx, bx
+            0xff, 0xff, // group ff /7, which doesn't exist
+            0x89, 0xd9, // mov cx, bx
+        ],
+    );
+    assert_eq!(
+        stdout,
+        "bits 16\nmov cx, bx\ndb 0xff\ndb 0xff\nmov cx, bx\n"
+    );
+}
+
+#[test]
+fn runs_code_the_program_wrote() {
+    let program = [
+        0x43, // inc bx
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0xc6, 0x06, 0x02, 0x00, 0x07, // mov byte [2], 7
+        0x83, 0xfb, 0x02, // cmp bx, 2
+        0x75, 0xf2, // jne $-12
+    ];
+    // the second time round, the mov has to be decoded again to see the 7
+    let stdout = run_with_stdin(&["-exec"], &program);
+    assert!(stdout.contains("mov cx, 7"), "{stdout}");
+    assert!(stdout.contains("cx: 0x0007 (7)"), "{stdout}");
+
+    // the same with the program somewhere else
+    let program = [
+        0xc6, 0x06, 0x06, 0x01, 0x05, // mov byte [0x106], 5
+        0xb9, 0x03, 0x00, // mov cx, 3
+    ];
+    let stdout = run_with_stdin(&["-exec", "--origin", "0x100"], &program);
+    assert!(stdout.contains("cx: 0x0005 (5)"), "{stdout}");
+}
+
+#[test]
+fn reads_data_next_to_the_code() {
+    let program = [
+        0xa1, 0x05, 0x00, // mov ax, [5]
+        0x75, 0x02, // jne $+4, zf is clear
+        0x34, 0x12, // dw 0x1234
+    ];
+    let stdout = run_with_stdin(&["-exec"], &program);
+    assert!(stdout.contains("ax: 0x1234 (4660)"), "{stdout}");
+}
@@ -0,0 +1,228 @@
+use sim::{
+    Config, ConfigError, Dump, Mode, RegIndex, Render, Syntax, MEMORY_SIZE, SEGMENTED_MEMORY_SIZE,
+};
+
+fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+    Config::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn parses_modes_and_flags() {
+    let config = parse(&["prog.bin", "-exec", "--trace", "--origin", "0x100"]).unwrap();
+    assert_eq!(
+        config,
+        Config {
+            mode: Mode::Simulate,
+            files: vec!["prog.bin".into()],
+            image: false,
+            byte_registers: false,
+            stats: false,
+            segmented: false,
+            labels: false,
+            listing: false,
+            addresses: false,
+            skip_unknown: false,
+            count: false,
+            syntax: Syntax::Intel,
+            trace: true,
+            trace_addresses: false,
+            cycle_estimate: false,
+            cycle_estimate_8088: false,
+            origin: 0x100,
+            range: None,
+            output: None,
+            registers: vec![],
+            stack_floor: None,
+            max_instructions: None,
+            breakpoints: vec![],
+            break_stop: false,
+            step: false,
+            dumps: vec![],
+            renders: vec![],
+            verify: None,
+            help: false,
+        }
+    );
+}
+
+#[test]
+fn defaults_to_decoding_stdin() {
+    let config = parse(&["--origin=256"]).unwrap();
+    assert_eq!(config.mode, Mode::Decode);
+    assert_eq!(config.files, ["-"]);
+    assert_eq!(config.origin, 256);
+    assert_eq!(config.output, None);
+    assert_eq!(config.syntax, Syntax::Intel);
+
+    let config = parse(&["-o", "out.asm", "prog.bin"]).unwrap();
+    assert_eq!(config.output.as_deref(), Some("out.asm"));
+    assert_eq!(config.files, ["prog.bin"]);
+
+    let config = parse(&["--range", "0x10:32"]).unwrap();
+    assert_eq!(config.range, Some(0x10..32));
+    assert_eq!(
+        parse(&["--range=32:16"]),
+        Err(ConfigError::BadValue {
+            flag: "--range=32:16".into(),
+            value: "32:16".into()
+        })
+    );
+
+    let config = parse(&["--syntax", "att"]).unwrap();
+    assert_eq!(config.syntax, Syntax::Att);
+    assert_eq!(
+        parse(&["--syntax=gas"]),
+        Err(ConfigError::BadValue {
+            flag: "--syntax=gas".into(),
+            value: "gas".into()
+        })
+    );
+}
+
+#[test]
+fn rejects_bad_flags() {
+    assert_eq!(
+        parse(&["-bogus"]),
+        Err(ConfigError::UnknownFlag("-bogus".into()))
+    );
+    assert_eq!(
+        parse(&["--origin"]),
+        Err(ConfigError::MissingValue("--origin".into()))
+    );
+    assert_eq!(
+        parse(&["--origin", "lots"]),
+        Err(ConfigError::BadValue {
+            flag: "--origin".into(),
+            value: "lots".into()
+        })
+    );
+    assert_eq!(
+        parse(&["--trace"]),
+        Err(ConfigError::NeedsExec("--trace".into()))
+    );
+    assert_eq!(
+        parse(&["--exec", "--labels"]),
+        Err(ConfigError::Conflict("--labels".into(), "--exec".into()))
+    );
+    assert_eq!(
+        parse(&["--count", "--labels"]),
+        Err(ConfigError::Conflict("--count".into(), "--labels".into()))
+    );
+    assert_eq!(
+        parse(&["--addresses", "--listing"]),
+        Err(ConfigError::Conflict(
+            "--addresses".into(),
+            "--listing".into()
+        ))
+    );
+    assert_eq!(
+        parse(&["--exec", "--skip-unknown"]),
+        Err(ConfigError::Conflict(
+            "--skip-unknown".into(),
+            "--exec".into()
+        ))
+    );
+    assert_eq!(
+        parse(&["--exec", "--step"]),
+        Err(ConfigError::Conflict(
+            "--step".into(),
+            "a program on stdin".into()
+        ))
+    );
+}
+
+#[test]
+fn parses_dump_ranges() {
+    let config = parse(&["-exec", "--dump", "0x100:16:out.bin", "--dump=0:2:a:b"]).unwrap();
+    assert_eq!(
+        config.dumps,
+        [
+            Dump {
+                start: 0x100,
+                len: 16,
+                file: "out.bin".into()
+            },
+            Dump {
+                start: 0,
+                len: 2,
+                file: "a:b".into()
+            },
+        ]
+    );
+}
+
+#[test]
+fn rejects_dumps_past_the_end_of_memory() {
+    let arg = format!("{}:2:out.bin", MEMORY_SIZE - 1);
+    assert_eq!(
+        parse(&["-exec", "--dump", &arg]),
+        Err(ConfigError::OutOfRange {
+            start: MEMORY_SIZE - 1,
+            len: 2
+        })
+    );
+    // there's more memory to dump when it's segmented, whichever flag comes
+    // first
+    assert!(parse(&["-exec", "--dump", &arg, "--segmented"]).is_ok());
+    let arg = format!("{}:2:out.bin", SEGMENTED_MEMORY_SIZE - 1);
+    assert_eq!(
+        parse(&["-exec", "--segmented", "--dump", &arg]),
+        Err(ConfigError::OutOfRange {
+            start: SEGMENTED_MEMORY_SIZE - 1,
+            len: 2
+        })
+    );
+    assert_eq!(
+        parse(&["-exec", "--dump", "0:2"]),
+        Err(ConfigError::BadValue {
+            flag: "--dump".into(),
+            value: "0:2".into()
+        })
+    );
+}
+
+#[test]
+fn parses_render_sizes() {
+    let config = parse(&["-exec", "--render", "64x64:0x100:out.ppm"]).unwrap();
+    assert_eq!(
+        config.renders,
+        [Render {
+            width: 64,
+            height: 64,
+            start: 0x100,
+            file: "out.ppm".into()
+        }]
+    );
+    for value in ["64:0x100:out.ppm", "64x64:0x100", "64xx:0:out.ppm"] {
+        assert_eq!(
+            parse(&["-exec", "--render", value]),
+            Err(ConfigError::BadValue {
+                flag: "--render".into(),
+                value: value.into(),
+            })
+        );
+    }
+    assert_eq!(
+        parse(&["--render", "1x1:0:out.ppm"]),
+        Err(ConfigError::NeedsExec("--render".into()))
+    );
+}
+
+#[test]
+fn parses_initial_registers() {
+    let config = parse(&["--exec", "--set", "bx=1000", "--set=AL=0x12"]).unwrap();
+    assert_eq!(
+        config.registers,
+        [(RegIndex::BX, 1000), (RegIndex::AL, 0x12)]
+    );
+
+    for value in ["al=256", "xx=1", "bx"] {
+        assert_eq!(
+            parse(&["--exec", "--set", value]),
+            Err(ConfigError::BadValue {
+                flag: "--set".into(),
+                value: value.into(),
+            })
+        );
+    }
+}
@@ -0,0 +1,941 @@
+use sim::{
+    decode, decode_first_at, disassemble, Add, DecodeCache, DecodeError, Decoder, EABase,
+    Instruction, Loc, Mov, Op, ReadDecoder, Reg, RegIndex, Rep, Str, StrOp, Syntax, Width, EAC,
+};
+
+fn read_listing(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+// the listing's own source, minus comments, blank lines and the `bits 16`
+fn read_listing_asm(name: &str) -> Vec<String> {
+    let path = format!("{}/../{}.asm", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';') && *line != "bits 16")
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn decodes_register_movs() {
+    for name in [
+        "listing_0037_single_register_mov",
+        "listing_0038_many_register_mov",
+    ] {
+        let asm = decode(&read_listing(name))
+            .unwrap()
+            .iter()
+            .map(|inst| inst.asm())
+            .collect::<Vec<_>>();
+        assert_eq!(asm, read_listing_asm(name), "{name}");
+    }
+}
+
+// the ones whose source is written the same way the decoder prints it
+#[test]
+fn decodes_listings_back_to_their_source() {
+    for name in [
+        "listing_0043_immediate_movs",
+        "listing_0044_register_movs",
+        "listing_0048_ip_register",
+        "listing_0057_challenge_cycles",
+    ] {
+        let bytes = read_listing(name);
+        let lines = disassemble(&bytes, false, Syntax::Intel).unwrap();
+        assert_eq!(lines, read_listing_asm(name), "{name}");
+
+        // and starting from each instruction finds the same one again
+        for next in Decoder::new(&bytes) {
+            let (inst, range) = next.unwrap();
+            assert_eq!(
+                decode_first_at(&bytes, range.start),
+                Ok((inst, range.len()))
+            );
+        }
+    }
+}
+
+#[test]
+fn immediates_only_get_a_size_for_memory() {
+    let asm = decode(&[
+        0xb8, 0x2a, 0x00, // mov ax, 42
+        0xc7, 0x07, 0x2a, 0x00, // mov word [bx], 42
+        0x80, 0x07, 0x2a, // add byte [bx], 42
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(
+        asm,
+        ["mov ax, 42", "mov word [bx], 42", "add byte [bx], 42"]
+    );
+}
+
+#[test]
+fn decodes_segment_register_movs() {
+    let asm = decode(&[
+        0x8e, 0xd8, // mov ds, ax
+        0x8c, 0x07, // mov [bx], es
+        0x8e, 0xc0, // mov es, ax
+        0x8c, 0xdb, // mov bx, ds
+        0x8e, 0x56, 0x02, // mov ss, [bp + 2]
+        0x8c, 0xc9, // mov cx, cs
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(
+        asm,
+        [
+            "mov ds, ax",
+            "mov [bx], es",
+            "mov es, ax",
+            "mov bx, ds",
+            "mov ss, [bp + 2]",
+            "mov cx, cs",
+        ]
+    );
+
+    // the bit above SR would pick a fifth segment register, which the 8086
+    // doesn't have
+    assert!(decode(&[0x8c, 0xe0]).is_err());
+}
+
+#[test]
+fn decodes_adc_and_sbb() {
+    let asm = decode(&[
+        0x11, 0xd8, // adc ax, bx
+        0x83, 0x1f, 0x05, // sbb word [bx], 5
+        0x14, 0x07, // adc al, 7
+        0x1b, 0x4f, 0x02, // sbb cx, [bx + 2]
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(
+        asm,
+        [
+            "adc ax, bx",
+            "sbb word [bx], 5",
+            "adc al, 7",
+            "sbb cx, [bx + 2]"
+        ]
+    );
+}
+
+#[test]
+fn decodes_all_of_group_ff() {
+    let asm = decode(&[
+        0xfe, 0x07, // inc byte [bx]
+        0xff, 0x0f, // dec word [bx]
+        0xff, 0xd0, // call ax
+        0xff, 0x57, 0x02, // call word [bx + 2]
+        0xff, 0x1f, // call far [bx]
+        0xff, 0xe1, // jmp cx
+        0xff, 0x27, // jmp word [bx]
+        0xff, 0x2e, 0xe8, 0x03, // jmp far [1000]
+        0xff, 0x37, // push word [bx]
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(
+        asm,
+        [
+            "inc byte [bx]",
+            "dec word [bx]",
+            "call ax",
+            "call word [bx + 2]",
+            "call far [bx]",
+            "jmp cx",
+            "jmp word [bx]",
+            "jmp far [1000]",
+            "push word [bx]",
+        ]
+    );
+
+    // /7 isn't anything, only inc and dec take bytes, and a far pointer
+    // can't be in a register
+    for mod_rm in [[0xff, 0x3f], [0xfe, 0x17], [0xff, 0xd8]] {
+        assert_eq!(
+            decode(&mod_rm).err(),
+            Some(DecodeError::UnsupportedModRm {
+                opcode: mod_rm[0],
+                mod_rm: mod_rm[1]
+            })
+        );
+    }
+}
+
+#[test]
+fn immediate_group_has_all_eight_ops() {
+    let asm = |bytes: &[u8]| decode(bytes).unwrap()[0].asm();
+    let ops = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+    for (op_bits, op) in (0u8..).zip(ops) {
+        let mod_rm = 0b_1100_0011 | op_bits << 3; // bx
+                                                  // sign extended to 0xffff
+        assert_eq!(asm(&[0x83, mod_rm, 0xff]), format!("{op} bx, 65535"));
+        // the full word
+        assert_eq!(asm(&[0x81, mod_rm, 0x0f, 0xff]), format!("{op} bx, 65295"));
+        // byte [bx]
+        assert_eq!(
+            asm(&[0x80, op_bits << 3 | 0b_111, 0x0f]),
+            format!("{op} byte [bx], 15")
+        );
+    }
+}
+
+#[test]
+fn decodes_in_and_out() {
+    let asm = decode(&[
+        0xe4, 0x3c, // in al, 60
+        0xe5, 0x3c, // in ax, 60
+        0xe6, 0x3c, // out 60, al
+        0xe7, 0x3c, // out 60, ax
+        0xec, // in al, dx
+        0xed, // in ax, dx
+        0xee, // out dx, al
+        0xef, // out dx, ax
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(
+        asm,
+        [
+            "in al, 60",
+            "in ax, 60",
+            "out 60, al",
+            "out 60, ax",
+            "in al, dx",
+            "in ax, dx",
+            "out dx, al",
+            "out dx, ax",
+        ]
+    );
+}
+
+#[test]
+fn decodes_bcd_adjusts() {
+    let asm = decode(&[
+        0x27, // daa
+        0x2f, // das
+        0x37, // aaa
+        0x3f, // aas
+        0xd4, 0x0a, // aam
+        0xd5, 0x0a, // aad
+        0xd4, 0x10, // aam 16
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(asm, ["daa", "das", "aaa", "aas", "aam", "aad", "aam 16"]);
+}
+
+#[test]
+fn decodes_to_structure() {
+    // mov ax, bx
+    assert_eq!(
+        decode(&[0x89, 0xd8]).unwrap()[0].op,
+        Op::Mov(Mov {
+            src: Loc::Reg(RegIndex::BX),
+            dst: Loc::Reg(RegIndex::AX),
+        })
+    );
+}
+
+#[test]
+fn sign_extends_byte_immediates() {
+    // add word [bx], -1, with s=1 so the immediate is one byte
+    assert_eq!(
+        decode(&[0x83, 0x07, 0xff]).unwrap()[0].op,
+        Op::Add(Add {
+            src: Loc::Imm16(0xffff),
+            dst: Loc::EAC(EAC {
+                base: EABase::Bx,
+                displacement: None,
+                segment: None,
+            }),
+        })
+    );
+}
+
+#[test]
+fn immediate_to_direct_address() {
+    // mov word [1234], 5, where r/m 110 with mod 00 isn't [bp]
+    assert_eq!(
+        decode(&[0xc7, 0x06, 0xd2, 0x04, 0x05, 0x00]).unwrap()[0].op,
+        Op::Mov(Mov {
+            src: Loc::Imm16(5),
+            dst: Loc::EAC(EAC {
+                base: EABase::DirectAddr(1234),
+                displacement: None,
+                segment: None,
+            }),
+        })
+    );
+}
+
+#[test]
+fn decodes_call_and_ret() {
+    let asm = decode(&[
+        0xe8, 0x05, 0x00, // call $+8
+        0xc3, // ret
+        0xc2, 0x02, 0x00, // ret 2
+        0xf4, // hlt
+    ])
+    .unwrap()
+    .iter()
+    .map(|inst| inst.asm())
+    .collect::<Vec<_>>();
+    assert_eq!(asm, ["call $+8", "ret", "ret 2", "hlt"]);
+}
+
+#[test]
+fn every_jump_opcode_decodes_as_a_jump() {
+    let mnemonics = [
+        "jo", "jno", "jb", "jnb", "je", "jnz", "jbe", "ja", "js", "jns", "jp", "jnp", "jl", "jnl",
+        "jle", "jg",
+    ];
+    let opcodes = (0x70..=0x7f).chain(0xe0..=0xe3);
+    let mnemonics = mnemonics
+        .into_iter()
+        .chain(["loopnz", "loopz", "loop", "jcxz"]);
+    for (b, mnemonic) in opcodes.zip(mnemonics) {
+        let inst = decode(&[b, 0x10]).unwrap().remove(0);
+        assert!(matches!(inst.op, Op::Jump(_)), "{b:#04x}: {inst:?}");
+        assert_eq!(inst.asm(), format!("{mnemonic} $+18"), "{b:#04x}");
+    }
+}
+
+#[test]
+fn jump_targets_get_labels() {
+    let name = "listing_0050_challenge_jumps";
+    let labelled = |lines: Vec<String>| {
+        lines
+            .into_iter()
+            .enumerate()
+            .filter(|(_, line)| line.contains("label_"))
+            .collect::<Vec<_>>()
+    };
+    let lines = disassemble(&read_listing(name), false, Syntax::Intel).unwrap();
+    assert_eq!(labelled(lines), labelled(read_listing_asm(name)));
+}
+
+#[test]
+fn jump_into_the_middle_of_an_instruction_keeps_the_offset() {
+    let lines = disassemble(
+        &[
+            0xb8, 0x01, 0x00, // mov ax, 1
+            0x75, 0xfc, // jnz $-2, into the mov's immediate
+        ],
+        false,
+        Syntax::Intel,
+    )
+    .unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1], "jnz $-2");
+}
+
+#[test]
+fn truncated_instruction_is_an_error() {
+    // mov ax, 0x1234 missing its high byte
+    assert_eq!(
+        decode(&[0xb8, 0x34]).err(),
+        Some(DecodeError::UnexpectedEof)
+    );
+}
+
+#[test]
+fn truncated_displacement_is_an_error() {
+    // mov ax, [bx + 1234] that stops right after the mod r/m byte
+    assert_eq!(
+        decode(&[0x8b, 0x87]).err(),
+        Some(DecodeError::UnexpectedEof)
+    );
+    // and one that only has the low byte of the displacement
+    assert_eq!(
+        decode(&[0x8b, 0x87, 0xd2]).err(),
+        Some(DecodeError::UnexpectedEof)
+    );
+}
+
+// first bytes that aren't decoded yet: segment pushes and pops, lock, lea
+// and so on. the other 256 - 54 all have to lead to a parser, even if the
+// byte after is wrong for it
+const UNDECODED: [u8; 54] = [
+    0x06, 0x07, 0x0e, 0x0f, 0x16, 0x17, 0x1e, 0x1f, 0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67,
+    0x68, 0x69, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x8d, 0x98, 0x99, 0x9a, 0x9e, 0x9f, 0xc0, 0xc1,
+    0xc4, 0xc5, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd, 0xce, 0xcf, 0xd6, 0xd7, 0xe9, 0xea, 0xeb, 0xf0,
+    0xf1, 0xf5, 0xf8, 0xf9, 0xfa, 0xfb,
+];
+
+#[test]
+fn every_opcode_is_decoded_or_unknown() {
+    for b in 0..=255 {
+        let result = decode_first_at(&[b, 0, 0, 0, 0, 0], 0);
+        let is_unknown = result == Err(DecodeError::UnknownOpcode(b));
+        assert_eq!(is_unknown, UNDECODED.contains(&b), "{b:#04x}: {result:?}");
+    }
+}
+
+#[test]
+fn undefined_opcode_is_an_error() {
+    // 0x0f is pop cs on the 8086, which never worked and isn't decoded
+    assert_eq!(
+        decode(&[0x89, 0xd9, 0x0f]).err(),
+        Some(DecodeError::UnknownOpcode(0x0f))
+    );
+}
+
+#[test]
+fn unsupported_group_op_is_an_error() {
+    // 0xf6 /2 is `not`
+    assert_eq!(
+        decode(&[0xf6, 0xd0]).err(),
+        Some(DecodeError::UnsupportedModRm {
+            opcode: 0xf6,
+            mod_rm: 0xd0
+        })
+    );
+}
+
+#[test]
+fn decoder_ranges_tile_the_input() {
+    let bytes = read_listing("listing_0055_challenge_rectangle");
+    let mut end = 0;
+    for next in Decoder::new(&bytes) {
+        let (inst, range) = next.unwrap();
+        assert_eq!(range.start, end, "{}", inst.asm());
+        assert_eq!(range.len(), inst.size(), "{}", inst.asm());
+        end = range.end;
+    }
+    assert_eq!(end, bytes.len());
+
+    // and it stops at the first error
+    let mut decoder = Decoder::new(&[0x89, 0xd9, 0x0f, 0x89, 0xd9]);
+    assert_eq!(decoder.next().unwrap().unwrap().1, 0..2);
+    assert_eq!(
+        decoder.next().unwrap(),
+        Err(DecodeError::UnknownOpcode(0x0f))
+    );
+    assert_eq!(decoder.next(), None);
+}
+
+#[test]
+fn instruction_bytes_put_back_together_are_the_input() {
+    // 42 has bytes that don't decode, which still get a range each
+    for name in [
+        "listing_0042_completionist_decode",
+        "listing_0055_challenge_rectangle",
+        "listing_0057_challenge_cycles",
+    ] {
+        let bytes = read_listing(name);
+        let joined = Decoder::new(&bytes)
+            .skipping_unknown()
+            .flat_map(|next| bytes[next.unwrap().1].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(joined, bytes, "{name}");
+    }
+}
+
+// hands out a few bytes per read, so instructions get split across them
+struct Trickle<'a> {
+    bytes: &'a [u8],
+    per_read: usize,
+}
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.bytes.is_empty() && self.per_read == 0 {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        let n = self.per_read.min(buf.len()).min(self.bytes.len());
+        buf[..n].copy_from_slice(&self.bytes[..n]);
+        self.bytes = &self.bytes[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn read_decoder_matches_decoding_all_at_once() {
+    let mut bytes = read_listing("listing_0041_add_sub_cmp_jnz");
+    // the longest there is, with a prefix on the front
+    bytes.extend([0x26, 0xc7, 0x87, 0xe8, 0x03, 0x05, 0x00]);
+    let want = Decoder::new(&bytes).collect::<Vec<_>>();
+    for per_read in [1, 3, 7, 4096] {
+        let got = ReadDecoder::new(Trickle {
+            bytes: &bytes,
+            per_read,
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(got, want, "{per_read} bytes at a time");
+    }
+
+    // an error reading is the end of it
+    let mut decoder = ReadDecoder::new(Trickle {
+        bytes: &[],
+        per_read: 0,
+    });
+    assert_eq!(
+        decoder.next(),
+        Some(Err(DecodeError::Io(std::io::ErrorKind::BrokenPipe)))
+    );
+    assert_eq!(decoder.next(), None);
+}
+
+#[test]
+fn errors_say_where_they_happened() {
+    let mut bytes = read_listing("listing_0041_add_sub_cmp_jnz");
+    // on an instruction boundary, partway through
+    let offset = Decoder::new(&bytes).nth(20).unwrap().unwrap().1.start;
+    bytes.insert(offset, 0x0f);
+
+    let mut decoder = Decoder::new(&bytes);
+    let err = decoder.by_ref().find_map(Result::err).unwrap();
+    let err = decoder.locate(err);
+    let DecodeError::At {
+        offset: got,
+        context,
+        ..
+    } = &err
+    else {
+        panic!("{err:?}");
+    };
+    assert_eq!(*got, offset);
+    let hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let after = format!("[0F] {}", hex(&bytes[offset + 1..=offset + 4]));
+    let want = format!("{} {after}", hex(&bytes[offset - 4..offset]));
+    assert_eq!(*context, want);
+    assert_eq!(
+        err.to_string(),
+        format!("unknown opcode 0b00001111 at offset {offset:#x} (context: {want})")
+    );
+    assert_eq!(
+        disassemble(&bytes, false, Syntax::Intel)
+            .unwrap_err()
+            .to_string(),
+        err.to_string()
+    );
+
+    // only what comes after is still around to show
+    let mut decoder = ReadDecoder::new(Trickle {
+        bytes: &bytes,
+        per_read: 3,
+    });
+    let err = decoder.by_ref().find_map(Result::err).unwrap();
+    assert_eq!(
+        decoder.locate(err).to_string(),
+        format!("unknown opcode 0b00001111 at offset {offset:#x} (context: {after})")
+    );
+}
+
+#[test]
+fn skipping_unknown_keeps_going_past_data() {
+    let bytes = [
+        0x89, 0xd9, // mov cx, bx
+        0x0f, // not an instruction
+        0x75, 0xfb, // jnz back to the start
+        0xb8, 0x34, // mov ax, missing its high byte
+    ];
+    let decoded = Decoder::new(&bytes)
+        .skipping_unknown()
+        .map(|next| next.map(|(inst, range)| (inst.asm(), range)))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        decoded,
+        [
+            ("mov cx, bx".to_string(), 0..2),
+            ("db 0x0f".to_string(), 2..3),
+            ("jnz $-3".to_string(), 3..5),
+            ("db 0xb8".to_string(), 5..6),
+            ("db 0x34".to_string(), 6..7),
+        ]
+    );
+
+    // jump targets still line up, with the data counted in
+    let lines = disassemble(&bytes[..5], true, Syntax::Intel).unwrap();
+    assert_eq!(lines, ["label_0:", "mov cx, bx", "db 0x0f", "jnz label_0"]);
+    assert!(disassemble(&bytes, false, Syntax::Intel).is_err());
+}
+
+#[test]
+fn decodes_segment_prefixes() {
+    let bytes = [
+        0x26, 0x8a, 0x00, // mov al, [es:bx + si]
+        0x2e, 0xff, 0x57, 0x02, // call word [cs:bx + 2]
+        0x36, 0xa1, 0xe8, 0x03, // mov ax, [ss:1000]
+        0x3e, 0xc6, 0x46, 0xfd, 0x05, // mov byte [ds:bp - 3], 5
+    ];
+    let insts = decode(&bytes).unwrap();
+    assert_eq!(
+        insts.iter().map(|inst| inst.asm()).collect::<Vec<_>>(),
+        [
+            "mov al, [es:bx + si]",
+            "call word [cs:bx + 2]",
+            "mov ax, [ss:1000]",
+            "mov byte [ds:bp - 3], 5",
+        ]
+    );
+    assert_eq!(
+        insts.iter().map(|inst| inst.size()).sum::<usize>(),
+        bytes.len()
+    );
+    assert_eq!(Syntax::Att.asm(&insts[0]), "movb %es:(%bx,%si), %al");
+
+    // nothing in memory to apply it to, or it's already been applied
+    assert_eq!(
+        decode(&[0x26, 0x89, 0xd8]),
+        Err(DecodeError::StrayPrefix(0x26))
+    );
+    assert_eq!(
+        decode(&[0x26, 0x3e, 0x8a, 0x07]),
+        Err(DecodeError::StrayPrefix(0x26))
+    );
+}
+
+#[test]
+fn decodes_string_ops() {
+    let bytes = [
+        0xa4, // movsb
+        0xf3, 0xa5, // rep movsw
+        0xf3, 0xa6, // repe cmpsb
+        0xf2, 0xaf, // repne scasw
+        0xaa, // stosb
+        0xf3, 0xab, // rep stosw
+        0xac, // lodsb
+        0xad, // lodsw
+    ];
+    let insts = decode(&bytes).unwrap();
+    assert_eq!(
+        insts.iter().map(|inst| inst.asm()).collect::<Vec<_>>(),
+        [
+            "movsb",
+            "rep movsw",
+            "repe cmpsb",
+            "repne scasw",
+            "stosb",
+            "rep stosw",
+            "lodsb",
+            "lodsw",
+        ]
+    );
+    assert_eq!(
+        insts.iter().map(|inst| inst.size()).sum::<usize>(),
+        bytes.len()
+    );
+
+    // rep only goes with a string op, and only once
+    assert_eq!(
+        decode(&[0xf3, 0x89, 0xd8]),
+        Err(DecodeError::StrayPrefix(0xf3))
+    );
+    assert_eq!(
+        decode(&[0xf2, 0xf3, 0xa4]),
+        Err(DecodeError::StrayPrefix(0xf2))
+    );
+    assert_eq!(decode(&[0xf3]), Err(DecodeError::UnexpectedEof));
+}
+
+#[test]
+fn bp_addresses_default_to_the_stack_segment() {
+    let insts = decode(&[
+        0x8b, 0x46, 0x04, // mov ax, [bp + 4]
+        0x8b, 0x07, // mov ax, [bx]
+        0x8b, 0x02, // mov ax, [bp + si]
+        0xa1, 0xe8, 0x03, // mov ax, [1000]
+    ])
+    .unwrap();
+    let segments = insts
+        .iter()
+        .map(|inst| match inst.op {
+            Op::Mov(Mov {
+                src: Loc::EAC(eac), ..
+            }) => eac.default_segment(),
+            inst => panic!("{inst:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(segments, [Reg::SS, Reg::DS, Reg::SS, Reg::DS]);
+}
+
+#[test]
+fn decodes_wait_and_esc() {
+    let bytes = [
+        0x9b, // wait
+        0xd9, 0x06, 0xe8, 0x03, // esc 8, [1000], which is fld dword [1000]
+        0xdd, 0xd8, // esc 43, ax, which is fstp st0
+        0x26, 0xdf, 0x2f, // esc 61, [es:bx], which is fild qword [es:bx]
+    ];
+    let insts = decode(&bytes).unwrap();
+    assert_eq!(
+        insts.iter().map(|inst| inst.asm()).collect::<Vec<_>>(),
+        ["wait", "esc 8, [1000]", "esc 43, ax", "esc 61, [es:bx]"]
+    );
+    assert_eq!(
+        insts.iter().map(|inst| inst.size()).sum::<usize>(),
+        bytes.len()
+    );
+}
+
+// there's more than one way to encode a lot of instructions, and these are
+// all longer than the one nasm picks
+#[test]
+fn size_is_how_long_the_encoding_really_was() {
+    for (bytes, asm) in [
+        (&[0x8b, 0x06, 0xe8, 0x03][..], "mov ax, [1000]"),
+        (&[0x81, 0xc3, 0x05, 0x00], "add bx, 5"),
+        (&[0xff, 0xf0], "push ax"),
+        (&[0x87, 0xc3], "xchg ax, bx"),
+        (&[0x8b, 0x87, 0x04, 0x00], "mov ax, [bx + 4]"),
+    ] {
+        let insts = decode(bytes).unwrap();
+        assert_eq!(insts.len(), 1, "{asm}");
+        assert_eq!(insts[0].asm(), asm);
+        assert_eq!(insts[0].size(), bytes.len(), "{asm}");
+        assert!(Instruction::from(insts[0].op).size() < bytes.len(), "{asm}");
+    }
+}
+
+#[test]
+fn decodes_a_segment_override_and_rep_together() {
+    // either order is the same instruction
+    for bytes in [[0x26, 0xf3, 0xa4], [0xf3, 0x26, 0xa4]] {
+        let insts = decode(&bytes).unwrap();
+        assert_eq!(
+            insts[0].op,
+            Op::Str(Str {
+                op: StrOp::Movs,
+                width: Width::Byte,
+                rep: Some(Rep::Rep),
+                segment: Some(Reg::ES),
+            })
+        );
+        assert_eq!(insts[0].asm(), "rep es movsb");
+        assert_eq!(insts[0].size(), 3);
+    }
+
+    // rep still needs a string op under the override
+    assert_eq!(
+        decode(&[0xf3, 0x26, 0x8a, 0x07]),
+        Err(DecodeError::StrayPrefix(0xf3))
+    );
+    assert_eq!(
+        decode(&[0x26, 0xf3, 0x26, 0xa4]),
+        Err(DecodeError::StrayPrefix(0x26))
+    );
+}
+
+#[test]
+fn writes_att_syntax() {
+    let insts = decode(&[
+        0x89, 0xd8, // mov ax, bx
+        0xc6, 0x07, 0x05, // mov byte [bx], 5
+        0x8b, 0x46, 0xfd, // mov ax, [bp - 3]
+        0x02, 0x00, // add al, [bx + si]
+        0xa1, 0xe8, 0x03, // mov ax, [1000]
+        0xd3, 0xe0, // shl ax, cl
+        0xff, 0x17, // call [bx]
+        0x75, 0xf0, // jnz $-14
+        0xe4, 0x60, // in al, 96
+    ])
+    .unwrap();
+    assert_eq!(Syntax::Intel.asm(&insts[0]), "mov ax, bx");
+    assert_eq!(
+        insts
+            .iter()
+            .map(|inst| Syntax::Att.asm(inst))
+            .collect::<Vec<_>>(),
+        [
+            "movw %bx, %ax",
+            "movb $5, (%bx)",
+            "movw -3(%bp), %ax",
+            "addb (%bx,%si), %al",
+            "movw 1000, %ax",
+            "shlw %cl, %ax",
+            "call *(%bx)",
+            "jnz .-14",
+            "inb $96, %al",
+        ]
+    );
+}
+
+#[test]
+fn sizes_add_up_to_the_listing_length() {
+    for name in [
+        "listing_0039_more_movs",
+        "listing_0040_challenge_movs",
+        "listing_0041_add_sub_cmp_jnz",
+        "listing_0049_conditional_jumps",
+        "listing_0055_challenge_rectangle",
+    ] {
+        let bytes = read_listing(name);
+        let size = decode(&bytes)
+            .unwrap()
+            .iter()
+            .map(|inst| inst.size())
+            .sum::<usize>();
+        assert_eq!(size, bytes.len(), "{name}");
+    }
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    // xorshift, with a fixed seed so a failure happens again on the next run
+    let mut state: u64 = 0x8086;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..1_000_000 {
+        let len = (next() % 16) as usize;
+        let bytes = (0..len).map(|_| next() as u8).collect::<Vec<_>>();
+        let result = std::panic::catch_unwind(|| {
+            for decoded in Decoder::new(&bytes).skipping_unknown() {
+                let (inst, _) = decoded.unwrap();
+                inst.asm();
+                Syntax::Att.asm(&inst);
+            }
+            decode(&bytes).ok();
+        });
+        assert!(result.is_ok(), "panicked on {bytes:02x?}");
+    }
+}
+
+#[test]
+fn decode_cache_matches_decoding_every_time() {
+    let program = read_listing("listing_0054_draw_rectangle");
+    let mut cache = DecodeCache::new(program.len());
+    // twice through, the second time all from the cache
+    for _ in 0..2 {
+        for (_, range) in Decoder::new(&program).map(Result::unwrap) {
+            assert_eq!(
+                cache.decode_at(&program, range.start),
+                decode_first_at(&program, range.start)
+            );
+        }
+    }
+    assert_eq!(
+        cache.decode_at(&program, program.len()),
+        Err(DecodeError::UnexpectedEof)
+    );
+}
+
+#[test]
+fn decode_cache_forgets_overwritten_instructions() {
+    // mov cx, 3; mov bx, 1000
+    let mut program = vec![0xb9, 0x03, 0x00, 0xbb, 0xe8, 0x03];
+    let mut cache = DecodeCache::new(program.len());
+    let (first, _) = cache.decode_at(&program, 0).unwrap();
+    cache.decode_at(&program, 3).unwrap();
+
+    // the immediate of the first one, which doesn't touch the second
+    program[2] = 0x01;
+    assert_eq!(cache.decode_at(&program, 0).unwrap().0, first);
+    cache.invalidate(2..3);
+    assert_eq!(cache.decode_at(&program, 0).unwrap().0.asm(), "mov cx, 259");
+    assert_eq!(
+        cache.decode_at(&program, 3).unwrap().0.asm(),
+        "mov bx, 1000"
+    );
+
+    // past the end is fine, there's nothing there to forget
+    cache.invalidate(4..100);
+    program[5] = 0x00;
+    assert_eq!(cache.decode_at(&program, 3).unwrap().0.asm(), "mov bx, 232");
+}
+
+#[test]
+fn register_mnemonics_round_trip() {
+    let regs = [
+        RegIndex::AL,
+        RegIndex::AH,
+        RegIndex::BL,
+        RegIndex::BH,
+        RegIndex::CL,
+        RegIndex::CH,
+        RegIndex::DL,
+        RegIndex::DH,
+        RegIndex::AX,
+        RegIndex::BX,
+        RegIndex::CX,
+        RegIndex::DX,
+        RegIndex::SP,
+        RegIndex::BP,
+        RegIndex::SI,
+        RegIndex::DI,
+        RegIndex::IP,
+        RegIndex::ES,
+        RegIndex::CS,
+        RegIndex::SS,
+        RegIndex::DS,
+    ];
+    for reg in regs {
+        // stored the way it's printed, so printing doesn't have to change it
+        assert_eq!(reg.asm(), reg.asm().to_lowercase());
+        assert_eq!(format!("{reg}"), reg.asm());
+        assert_eq!(RegIndex::from_mnemonic(reg.asm()), Some(reg));
+        let upper = reg.asm().to_uppercase();
+        assert_eq!(RegIndex::from_mnemonic(&upper), Some(reg), "{upper}");
+    }
+    for name in ["", "eax", "b", "bx "] {
+        assert_eq!(RegIndex::from_mnemonic(name), None, "{name:?}");
+    }
+}
+
+#[test]
+fn mnemonic_is_the_first_word_of_asm() {
+    let prefixes = ["rep", "repe", "repne", "es", "cs", "ss", "ds"];
+    for name in [
+        "listing_0042_completionist_decode",
+        "listing_0055_challenge_rectangle",
+    ] {
+        // 0042 has push cs and the like, which come out as db
+        let bytes = read_listing(name);
+        for next in Decoder::new(&bytes).skipping_unknown() {
+            let (inst, _) = next.unwrap();
+            let asm = inst.asm();
+            let first = asm.split_whitespace().find(|word| !prefixes.contains(word));
+            assert_eq!(first, Some(inst.mnemonic()), "{asm}");
+        }
+    }
+}
+
+#[test]
+fn display_matches_asm() {
+    for name in [
+        "listing_0041_add_sub_cmp_jnz",
+        "listing_0055_challenge_rectangle",
+    ] {
+        for inst in decode(&read_listing(name)).unwrap() {
+            assert_eq!(format!("{inst}"), inst.asm());
+        }
+    }
+
+    let eac = EAC {
+        base: EABase::BpSi,
+        displacement: Some(-4),
+        segment: Some(Reg::ES),
+    };
+    assert_eq!(format!("{eac}"), "[es:bp + si - 4]");
+    assert_eq!(format!("{}", Loc::EAC(eac)), eac.asm());
+    assert_eq!(format!("{}", Loc::Reg(RegIndex::AH)), "ah");
+    assert_eq!(format!("{}", RegIndex::SP), "sp");
+}
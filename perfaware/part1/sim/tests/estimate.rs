@@ -0,0 +1,268 @@
+use sim::{
+    decode, decode_first_at, estimate_8086, estimate_8086_exec, estimate_8086_parts, estimate_8088,
+    estimate_8088_exec, transfer_penalty_8088, Instruction, CPU,
+};
+
+fn total(name: &str, estimate: fn(&Instruction) -> Option<usize>) -> usize {
+    let path = format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name);
+    decode(&std::fs::read(path).unwrap())
+        .unwrap()
+        .iter()
+        .map(|inst| estimate(inst).unwrap())
+        .sum()
+}
+
+// totals from the course's reference output
+#[test]
+fn the_8088_pays_for_word_transfers() {
+    let name = "listing_0056_estimating_cycles";
+    assert_eq!(total(name, estimate_8086), 192);
+    assert_eq!(total(name, estimate_8088), 236);
+}
+
+#[test]
+fn the_8088_pays_for_read_modify_write_twice() {
+    assert_eq!(total("listing_0057_challenge_cycles", estimate_8088), 341);
+}
+
+#[test]
+fn estimates_sub_cmp_and_jumps() {
+    let insts = decode(&[
+        0x29, 0xcb, // sub bx, cx
+        0x3b, 0x76, 0x00, // cmp si, [bp]
+        0x83, 0x3f, 0x05, // cmp word [bx], 5
+        0x29, 0x08, // sub [bx + si], cx
+        0x75, 0xf4, // jnz back to the top
+        0xe2, 0xf2, // loop back to the top
+    ])
+    .unwrap();
+    let clocks = insts.iter().map(estimate_8086).collect::<Option<Vec<_>>>();
+    assert_eq!(clocks.unwrap(), [3, 14, 15, 23, 16, 17]);
+}
+
+// the breakdowns from listing 56's reference output
+#[test]
+fn splits_the_estimate_into_base_and_ea() {
+    let insts = decode(&[
+        0x8b, 0x16, 0xe8, 0x03, // mov dx, [1000]
+        0x89, 0x4a, 0x04, // mov [bp + si + 4], cx
+        0x89, 0xd9, // mov cx, bx
+    ])
+    .unwrap();
+    let parts = insts
+        .iter()
+        .map(estimate_8086_parts)
+        .collect::<Option<Vec<_>>>();
+    assert_eq!(parts.unwrap(), [(8, 6), (9, 12), (2, 0)]);
+    let penalties = insts.iter().map(transfer_penalty_8088).collect::<Vec<_>>();
+    assert_eq!(penalties, [4, 4, 0]);
+}
+
+// table 2-21's accumulator rows
+#[test]
+fn estimates_the_accumulator_forms() {
+    let insts = decode(&[
+        0x05, 0x05, 0x00, // add ax, 5
+        0x2c, 0x05, // sub al, 5
+        0x3d, 0x05, 0x00, // cmp ax, 5
+        0x02, 0x07, // add al, [bx]
+        0x2b, 0x07, // sub ax, [bx]
+        0xa1, 0xe8, 0x03, // mov ax, [1000]
+        0xa2, 0xe8, 0x03, // mov [1000], al
+        0x8b, 0x07, // mov ax, [bx]
+        0x88, 0x07, // mov [bx], al
+    ])
+    .unwrap();
+    let clocks = insts.iter().map(estimate_8086).collect::<Option<Vec<_>>>();
+    assert_eq!(clocks.unwrap(), [4, 4, 4, 14, 14, 10, 10, 13, 14]);
+}
+
+// the rest of table 2-21
+#[test]
+fn estimates_everything_that_decodes() {
+    let insts = decode(&[
+        0x21, 0xd8, // and ax, bx
+        0x32, 0x07, // xor al, [bx]
+        0xa8, 0x01, // test al, 1
+        0xf6, 0xc3, 0x01, // test bl, 1
+        0x93, // xchg ax, bx
+        0x87, 0x0f, // xchg [bx], cx
+        0x40, // inc ax
+        0xfe, 0xc3, // inc bl
+        0xff, 0x0f, // dec word [bx]
+        0x50, // push ax
+        0xff, 0x37, // push word [bx]
+        0x58, // pop ax
+        0x9c, // pushf
+        0x9d, // popf
+        0xe8, 0x00, 0x00, // call $+3
+        0xff, 0xd0, // call ax
+        0xff, 0x27, // jmp word [bx]
+        0xc3, // ret
+        0xc2, 0x02, 0x00, // ret 2
+        0xa4, // movsb
+        0xab, // stosw
+        0xe4, 0x60, // in al, 96
+        0xee, // out dx, al
+        0x27, // daa
+        0xd4, 0x0a, // aam
+    ])
+    .unwrap();
+    let clocks = insts.iter().map(estimate_8086).collect::<Option<Vec<_>>>();
+    assert_eq!(
+        clocks.unwrap(),
+        [3, 14, 4, 5, 3, 22, 2, 3, 20, 11, 21, 8, 10, 8, 19, 16, 23, 8, 12, 18, 11, 10, 8, 4, 83]
+    );
+
+    // how many times it goes round isn't known until it runs
+    let rep_movsb = decode(&[0xf3, 0xa4]).unwrap()[0];
+    assert_eq!(estimate_8086(&rep_movsb), None);
+}
+
+#[test]
+fn the_8088_pays_for_the_stack_and_strings_too() {
+    let insts = decode(&[
+        0x50, // push ax
+        0xff, 0x37, // push word [bx]
+        0x8f, 0x07, // pop word [bx]
+        0x9c, // pushf
+        0xe8, 0x00, 0x00, // call $+3
+        0xff, 0x17, // call word [bx]
+        0xff, 0x1f, // call far [bx]
+        0xff, 0x27, // jmp word [bx]
+        0xc3, // ret
+        0xa5, // movsw
+        0xab, // stosw
+        0xaa, // stosb
+        0xe5, 0x60, // in ax, 96
+        0x87, 0x0f, // xchg [bx], cx
+        0xff, 0x07, // inc word [bx]
+        0x20, 0x07, // and [bx], al
+    ])
+    .unwrap();
+    let penalties = insts.iter().map(transfer_penalty_8088).collect::<Vec<_>>();
+    assert_eq!(penalties, [4, 8, 8, 4, 4, 8, 16, 4, 4, 8, 4, 0, 4, 8, 8, 0]);
+
+    // and for every pass of a rep
+    let program = [
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0xf3, 0xab, // rep stosw
+    ];
+    let mut cpu = CPU::new();
+    let (mov, num_bytes) = decode_first_at(&program, 0).unwrap();
+    cpu.exec(mov, num_bytes).unwrap();
+    let (rep, _) = decode_first_at(&program, 3).unwrap();
+    assert_eq!(
+        estimate_8088_exec(&rep, &cpu) - estimate_8086_exec(&rep, &cpu),
+        3 * 4
+    );
+}
+
+#[test]
+fn sim_costs_every_pass_of_a_rep() {
+    let total = sim_total_8086(&[
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0xf3, 0xaa, // rep stosb
+    ]);
+    assert_eq!(total, 4 + 9 + 3 * 10);
+}
+
+// runs the program, adding up the clocks with everything that only shows up
+// once the register values are known
+fn sim_total_8086(program: &[u8]) -> usize {
+    let mut cpu = CPU::new();
+    let mut total = 0;
+    while (cpu.ip() as usize) < program.len() {
+        let (inst, num_bytes) = decode_first_at(program, cpu.ip() as usize).unwrap();
+        total += estimate_8086_exec(&inst, &cpu);
+        cpu.exec(inst, num_bytes).unwrap();
+    }
+    total
+}
+
+#[test]
+fn odd_addresses_cost_more_on_the_8086() {
+    let even = sim_total_8086(&[
+        0xbb, 0xe8, 0x03, // mov bx, 1000
+        0x8b, 0x0f, // mov cx, [bx]
+        0x01, 0x0f, // add [bx], cx
+    ]);
+    let odd = sim_total_8086(&[
+        0xbb, 0xe9, 0x03, // mov bx, 1001
+        0x8b, 0x0f, // mov cx, [bx]
+        0x01, 0x0f, // add [bx], cx
+    ]);
+    // one transfer for the mov, two for the add
+    assert_eq!(odd - even, 12);
+}
+
+#[test]
+fn odd_stack_and_string_addresses_cost_more_on_the_8086() {
+    let program = |low| {
+        [
+            0xbc, low, 0x03, // mov sp, 1000 or 1001
+            0xbe, low, 0x03, // mov si, 1000 or 1001
+            0xbf, low, 0x03, // mov di, 1000 or 1001
+            0xb9, 0x02, 0x00, // mov cx, 2
+            0x50, // push ax
+            0x58, // pop ax
+            0xa5, // movsw
+            0xad, // lodsw
+            0xab, // stosw
+            0xf3, 0xab, // rep stosw
+        ]
+    };
+    let even = sim_total_8086(&program(0xe8));
+    let odd = sim_total_8086(&program(0xe9));
+    // push, pop, both sides of the movsw, lodsw, stosw, and both passes of
+    // the rep
+    assert_eq!(odd - even, 4 * (1 + 1 + 2 + 1 + 1 + 2));
+}
+
+#[test]
+fn sim_total_matches_the_reference() {
+    let path = format!(
+        "{}/../listing_0057_challenge_cycles",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    assert_eq!(sim_total_8086(&std::fs::read(path).unwrap()), 289);
+}
+
+#[test]
+fn estimates_mul_and_shift_by_cl() {
+    let insts = decode(&[
+        0xf7, 0xe1, // mul cx
+        0xd3, 0xe0, // shl ax, cl
+    ])
+    .unwrap();
+    let clocks = insts.iter().map(estimate_8086).collect::<Option<Vec<_>>>();
+    assert_eq!(clocks.unwrap(), [118, 8]);
+
+    let total = sim_total_8086(&[
+        0xb1, 0x03, // mov cl, 3
+        0xd3, 0xe0, // shl ax, cl
+    ]);
+    assert_eq!(total, 4 + 8 + 4 * 3);
+}
+
+#[test]
+fn sim_costs_taken_and_not_taken_jumps() {
+    let total = sim_total_8086(&[
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0x83, 0xc0, 0x01, // add ax, 1
+        0xe2, 0xfb, // loop back to the add
+    ]);
+    // taken twice, then falls through
+    assert_eq!(total, 4 + 3 * 4 + 2 * 17 + 5);
+}
+
+#[test]
+fn sim_costs_jnz_by_whether_it_branched() {
+    let total = sim_total_8086(&[
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0x83, 0xe9, 0x01, // sub cx, 1
+        0x75, 0xfb, // jnz back to the sub
+    ]);
+    // 16 for each of the two taken, 4 for the one that falls through
+    assert_eq!(total, 4 + 3 * 4 + 2 * 16 + 4);
+}
@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sim::decode;
+
+// everything we can decode so far
+const LISTINGS: [&str; 19] = [
+    "listing_0037_single_register_mov",
+    "listing_0038_many_register_mov",
+    "listing_0039_more_movs",
+    "listing_0040_challenge_movs",
+    "listing_0041_add_sub_cmp_jnz",
+    "listing_0043_immediate_movs",
+    "listing_0044_register_movs",
+    "listing_0046_add_sub_cmp",
+    "listing_0047_challenge_flags",
+    "listing_0048_ip_register",
+    "listing_0049_conditional_jumps",
+    "listing_0050_challenge_jumps",
+    "listing_0051_memory_mov",
+    "listing_0052_memory_add_loop",
+    "listing_0053_add_loop_challenge",
+    "listing_0054_draw_rectangle",
+    "listing_0055_challenge_rectangle",
+    "listing_0056_estimating_cycles",
+    "listing_0057_challenge_cycles",
+];
+
+fn has_nasm() -> bool {
+    Command::new("nasm").arg("-v").output().is_ok()
+}
+
+fn assemble(src: &Path, out: &Path) -> Vec<u8> {
+    let status = Command::new("nasm")
+        .arg(src)
+        .arg("-o")
+        .arg(out)
+        .status()
+        .unwrap();
+    assert!(status.success(), "nasm failed on {}", src.display());
+    std::fs::read(out).unwrap()
+}
+
+fn roundtrip(dir: &Path, name: &str, want: &[u8]) {
+    let mut asm = String::from("bits 16\n");
+    for inst in decode(want).unwrap() {
+        asm += &inst.asm();
+        asm += "\n";
+    }
+    let ours = dir.join(format!("{name}.asm"));
+    std::fs::write(&ours, asm).unwrap();
+    let got = assemble(&ours, &dir.join(format!("{name}.got")));
+    assert_eq!(got, want, "{name}");
+}
+
+// one per test, since they run in parallel
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sim-{test}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn disassembly_reassembles_to_the_same_bytes() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("listings");
+    for name in LISTINGS {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(format!("{name}.asm"));
+        let want = assemble(&src, &dir.join(format!("{name}.want")));
+        roundtrip(&dir, name, &want);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn immediates_only_get_a_size_for_memory() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("immediates");
+    let want = [
+        0xb8, 0x2a, 0x00, // mov ax, 42
+        0xc7, 0x07, 0x2a, 0x00, // mov word [bx], 42
+        0xc6, 0x07, 0x2a, // mov byte [bx], 42
+    ];
+    roundtrip(&dir, "immediates", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn adc_and_sbb_reassemble() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("adc-sbb");
+    let want = [
+        0x11, 0xd8, // adc ax, bx
+        0x83, 0x1f, 0x05, // sbb word [bx], 5
+        0x14, 0x07, // adc al, 7
+        0x1b, 0x4f, 0x02, // sbb cx, [bx + 2]
+    ];
+    roundtrip(&dir, "adc-sbb", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn group_ff_reassembles() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("group-ff");
+    let want = [
+        0xfe, 0x07, // inc byte [bx]
+        0xff, 0x0f, // dec word [bx]
+        0xff, 0xd0, // call ax
+        0xff, 0x57, 0x02, // call word [bx + 2]
+        0xff, 0x1f, // call far [bx]
+        0xff, 0xe1, // jmp cx
+        0xff, 0x27, // jmp word [bx]
+        0xff, 0x2e, 0xe8, 0x03, // jmp far [1000]
+        0xff, 0x37, // push word [bx]
+    ];
+    roundtrip(&dir, "group-ff", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn segment_register_movs_reassemble() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("sreg-movs");
+    let want = [
+        0x8e, 0xc0, // mov es, ax
+        0x8c, 0xdb, // mov bx, ds
+        0x8e, 0x56, 0x02, // mov ss, [bp + 2]
+        0x8c, 0x0f, // mov [bx], cs
+    ];
+    roundtrip(&dir, "sreg-movs", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn segment_prefixes_reassemble() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("segment-prefixes");
+    let want = [
+        0x26, 0x8a, 0x07, // mov al, [es:bx]
+        0x2e, 0x89, 0x58, 0x04, // mov [cs:bx + si + 4], bx
+        0x36, 0xa1, 0xe8, 0x03, // mov ax, [ss:1000]
+        0x26, 0xff, 0x1f, // call far [es:bx]
+    ];
+    roundtrip(&dir, "segment-prefixes", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn in_and_out_reassemble() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("in-out");
+    let want = [
+        0xe4, 0x3c, // in al, 60
+        0xe7, 0x3c, // out 60, ax
+        0xed, // in ax, dx
+        0xee, // out dx, al
+    ];
+    roundtrip(&dir, "in-out", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bcd_adjusts_reassemble() {
+    if !has_nasm() {
+        eprintln!("nasm isn't on PATH, skipping");
+        return;
+    }
+
+    let dir = scratch_dir("bcd");
+    let want = [
+        0x27, // daa
+        0x2f, // das
+        0x37, // aaa
+        0x3f, // aas
+        0xd4, 0x0a, // aam
+        0xd5, 0x0a, // aad
+        0xd4, 0x10, // aam 16
+    ];
+    roundtrip(&dir, "bcd", &want);
+    std::fs::remove_dir_all(dir).unwrap();
+}
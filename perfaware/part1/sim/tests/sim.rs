@@ -0,0 +1,1113 @@
+use sim::{
+    decode, decode_first_at, flags_from_string, flags_from_word, flags_to_word, format_flags,
+    physical_address, ppm, Branch, ExecError, Flag, Loc, RegIndex, Stats, CPU,
+};
+
+fn run(program: &[u8]) -> Result<CPU, ExecError> {
+    run_on(CPU::new(), program)
+}
+
+fn run_on(mut cpu: CPU, program: &[u8]) -> Result<CPU, ExecError> {
+    while (cpu.ip() as usize) < program.len() {
+        let (inst, num_bytes) = decode_first_at(program, cpu.ip() as usize).unwrap();
+        if cpu.exec(inst, num_bytes)?.halted {
+            break;
+        }
+    }
+    Ok(cpu)
+}
+
+fn reg(cpu: &CPU, reg: RegIndex) -> u16 {
+    cpu.get_src(Loc::Reg(reg))
+}
+
+#[test]
+fn byte_register_writes_keep_the_other_half() {
+    let cpu = run(&[
+        0xb8, 0xcd, 0xab, // mov ax, 0xabcd
+        0xb0, 0x01, // mov al, 1
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0xab01);
+}
+
+#[test]
+fn byte_memory_access_touches_one_byte() {
+    let cpu = run(&[
+        0xc7, 0x06, 0xe8, 0x03, 0xcd, 0xab, // mov word [1000], 0xabcd
+        0xb9, 0xff, 0xff, // mov cx, 0xffff
+        0xb0, 0x12, // mov al, 0x12
+        0x88, 0x06, 0xe8, 0x03, // mov [1000], al
+        0x8a, 0x0e, 0xe8, 0x03, // mov cl, [1000]
+        0x8b, 0x16, 0xe8, 0x03, // mov dx, [1000]
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 0xff12);
+    assert_eq!(reg(&cpu, RegIndex::DX), 0xab12);
+}
+
+#[test]
+fn moves_through_segment_registers() {
+    let cpu = run(&[
+        0xbb, 0x34, 0x12, // mov bx, 0x1234
+        0x8e, 0xdb, // mov ds, bx
+        0x8c, 0xd9, // mov cx, ds
+        0x8c, 0x1e, 0xe8, 0x03, // mov [1000], ds
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::DS), 0x1234);
+    assert_eq!(reg(&cpu, RegIndex::CX), 0x1234);
+    assert_eq!(cpu.memory()[1000..1002], [0x34, 0x12]);
+}
+
+#[test]
+fn segments_move_memory_accesses() {
+    let mut cpu = CPU::new_segmented();
+    cpu.set_dest(Loc::Reg(RegIndex::DS), 0xf000);
+    cpu.set_dest(Loc::Reg(RegIndex::SS), 0x1000);
+    let cpu = run_on(
+        cpu,
+        &[
+            0xbb, 0x10, 0x00, // mov bx, 0x10
+            0xc7, 0x07, 0x34, 0x12, // mov word [bx], 0x1234
+            0xbd, 0x20, 0x00, // mov bp, 0x20
+            0xc6, 0x46, 0x00, 0x56, // mov byte [bp], 0x56
+            0xbc, 0x00, 0x01, // mov sp, 0x100
+            0x53, // push bx
+        ],
+    )
+    .unwrap();
+    // data goes through ds, and bp and the stack through ss
+    assert_eq!(cpu.memory()[0xf0010..0xf0012], [0x34, 0x12]);
+    assert_eq!(cpu.memory()[0x10020], 0x56);
+    assert_eq!(cpu.memory()[0x100fe..0x10100], [0x10, 0x00]);
+    assert_eq!(cpu.memory()[0x10..0x12], [0x00, 0x00]);
+}
+
+#[test]
+fn segment_prefixes_override_the_default() {
+    let mut cpu = CPU::new_segmented();
+    cpu.set_dest(Loc::Reg(RegIndex::ES), 0x2000);
+    cpu.set_dest(Loc::Reg(RegIndex::DS), 0x3000);
+    let cpu = run_on(
+        cpu,
+        &[
+            0xbb, 0x10, 0x00, // mov bx, 0x10
+            0x26, 0xc7, 0x07, 0x34, 0x12, // mov word [es:bx], 0x1234
+            0xbd, 0x20, 0x00, // mov bp, 0x20
+            0xb0, 0x56, // mov al, 0x56
+            0x3e, 0x88, 0x46, 0x00, // mov [ds:bp + 0], al
+        ],
+    )
+    .unwrap();
+    assert_eq!(cpu.memory()[0x20010..0x20012], [0x34, 0x12]);
+    assert_eq!(cpu.memory()[0x30020], 0x56);
+}
+
+#[test]
+fn the_top_of_flat_memory_is_there() {
+    let cpu = run(&[
+        0xc6, 0x06, 0xfd, 0xff, 0x56, // mov byte [0xfffd], 0x56
+        0xb8, 0x34, 0x12, // mov ax, 0x1234
+        0x50, // push ax, with sp wrapping from 0 to 0xfffe
+    ])
+    .unwrap();
+    assert_eq!(cpu.memory().len(), 0x10000);
+    assert_eq!(cpu.memory()[0xfffd..], [0x56, 0x34, 0x12]);
+    assert_eq!(reg(&cpu, RegIndex::SP), 0xfffe);
+}
+
+#[test]
+fn words_at_the_top_of_memory_load_back() {
+    let cpu = run(&[
+        0xc7, 0x06, 0xfe, 0xff, 0xef, 0xbe, // mov word [0xfffe], 0xbeef
+        0xa1, 0xfe, 0xff, // mov ax, [0xfffe]
+        0xc7, 0x06, 0xff, 0xff, 0x34, 0x12, // mov word [0xffff], 0x1234
+        0x8b, 0x1e, 0xff, 0xff, // mov bx, [0xffff]
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0xbeef);
+    // the high byte wraps around to offset 0
+    assert_eq!(reg(&cpu, RegIndex::BX), 0x1234);
+    assert_eq!(cpu.memory()[0xffff], 0x34);
+    assert_eq!(cpu.memory()[0], 0x12);
+}
+
+#[test]
+fn physical_address_is_segment_times_16_plus_offset() {
+    assert_eq!(physical_address(0x1234, 0x5678), 0x179b8);
+    assert_eq!(physical_address(0, 0xffff), 0xffff);
+    // past 1MB wraps back to the start
+    assert_eq!(physical_address(0xffff, 0x0010), 0);
+}
+
+#[test]
+fn add_sets_aux_carry_out_of_the_low_nibble() {
+    let cpu = run(&[
+        0xb0, 0x09, // mov al, 9
+        0x04, 0x09, // add al, 9
+    ])
+    .unwrap();
+    assert!(cpu.get_flag(Flag::AuxCarry));
+}
+
+#[test]
+fn byte_add_flags_come_from_the_byte() {
+    let cpu = run(&[
+        0xb8, 0x7f, 0xff, // mov ax, 0xff7f
+        0x04, 0x01, // add al, 1
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0xff80);
+    assert!(cpu.get_flag(Flag::Sign));
+    assert!(cpu.get_flag(Flag::Overflow));
+    assert!(!cpu.get_flag(Flag::Carry));
+
+    let cpu = run(&[
+        0xb0, 0x80, // mov al, 0x80
+        0x04, 0x80, // add al, 0x80
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0);
+    assert!(cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn adc_and_sbb_chain_the_carry() {
+    let cpu = run(&[
+        0xb8, 0xff, 0xff, // mov ax, 0xffff
+        0xba, 0x01, 0x00, // mov dx, 1
+        0x05, 0x01, 0x00, // add ax, 1
+        0x83, 0xd2, 0x00, // adc dx, 0
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0);
+    assert_eq!(reg(&cpu, RegIndex::DX), 2);
+    assert!(!cpu.get_flag(Flag::Carry));
+
+    let cpu = run(&[
+        0xba, 0x02, 0x00, // mov dx, 2
+        0x2d, 0x01, 0x00, // sub ax, 1
+        0x83, 0xda, 0x00, // sbb dx, 0
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0xffff);
+    assert_eq!(reg(&cpu, RegIndex::DX), 1);
+    assert!(!cpu.get_flag(Flag::Carry));
+
+    // the carry in is what pushes it over, and it still counts
+    let cpu = run(&[
+        0xb8, 0xff, 0x7f, // mov ax, 0x7fff
+        0xbb, 0xff, 0xff, // mov bx, 0xffff
+        0x83, 0xc3, 0x01, // add bx, 1
+        0x83, 0xd0, 0x00, // adc ax, 0
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x8000);
+    assert!(cpu.get_flag(Flag::Overflow));
+    assert!(cpu.get_flag(Flag::AuxCarry));
+
+    let cpu = run(&[
+        0xbb, 0xff, 0xff, // mov bx, 0xffff
+        0x83, 0xc3, 0x01, // add bx, 1
+        0x83, 0xd8, 0xff, // sbb ax, -1
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0);
+    assert!(cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Zero));
+}
+
+// sar keeps the sign, shr shifts a 0 in. by 1, OF is whether the sign
+// changed
+#[test]
+fn sar_carries_the_sign_in_and_shr_doesnt() {
+    let sar = run(&[
+        0xb8, 0x00, 0x80, // mov ax, 0x8000
+        0xd1, 0xf8, // sar ax, 1
+    ])
+    .unwrap();
+    assert_eq!(reg(&sar, RegIndex::AX), 0xc000);
+    assert!(sar.get_flag(Flag::Sign));
+    assert!(!sar.get_flag(Flag::Carry));
+    assert!(!sar.get_flag(Flag::Overflow));
+
+    let shr = run(&[
+        0xb8, 0x00, 0x80, // mov ax, 0x8000
+        0xd1, 0xe8, // shr ax, 1
+    ])
+    .unwrap();
+    assert_eq!(reg(&shr, RegIndex::AX), 0x4000);
+    assert!(!shr.get_flag(Flag::Sign));
+    assert!(!shr.get_flag(Flag::Carry));
+    assert!(shr.get_flag(Flag::Overflow));
+
+    // the bit shifted out goes to CF either way
+    for (shift, want) in [(0xf8, 0xc000), (0xe8, 0x4000)] {
+        let cpu = run(&[
+            0xb8, 0x01, 0x80, // mov ax, 0x8001
+            0xd1, shift, // sar or shr ax, 1
+        ])
+        .unwrap();
+        assert_eq!(reg(&cpu, RegIndex::AX), want);
+        assert!(cpu.get_flag(Flag::Carry));
+    }
+}
+
+// and, or, xor and test clear CF and OF, and set ZF, SF and PF from the
+// result
+#[test]
+fn logic_ops_clear_the_carry_and_overflow() {
+    // starting with CF and OF both set
+    let after = |program: &[u8]| {
+        let carry_and_overflow = [
+            0xb0, 0x80, // mov al, 0x80
+            0x04, 0x80, // add al, 0x80
+        ];
+        run(&[&carry_and_overflow[..], program].concat()).unwrap()
+    };
+
+    let cpu = after(&[
+        0xb8, 0xff, 0xf0, // mov ax, 0xf0ff
+        0x25, 0x0f, 0x0f, // and ax, 0x0f0f
+    ]);
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x000f);
+    assert_eq!(format_flags(&cpu.flags()), "P");
+
+    let cpu = after(&[
+        0x31, 0xc0, // xor ax, ax
+    ]);
+    assert_eq!(reg(&cpu, RegIndex::AX), 0);
+    assert_eq!(format_flags(&cpu.flags()), "PZ");
+
+    let cpu = after(&[
+        0x31, 0xc0, // xor ax, ax
+        0x35, 0x00, 0x80, // xor ax, 0x8000
+    ]);
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x8000);
+    assert_eq!(format_flags(&cpu.flags()), "PS");
+
+    // test only sets the flags, al's left as it was
+    let cpu = after(&[
+        0xb0, 0x0e, // mov al, 0x0e
+        0xa8, 0x01, // test al, 1
+    ]);
+    assert_eq!(reg(&cpu, RegIndex::AL), 0x0e);
+    assert_eq!(format_flags(&cpu.flags()), "PZ");
+
+    let cpu = after(&[
+        0xb0, 0x0f, // mov al, 0x0f
+        0xa8, 0x01, // test al, 1
+    ]);
+    assert_eq!(reg(&cpu, RegIndex::AL), 0x0f);
+    assert_eq!(format_flags(&cpu.flags()), "");
+}
+
+#[test]
+fn xchg_swaps_both_ways() {
+    let cpu = run(&[
+        0xb8, 0x01, 0x00, // mov ax, 1
+        0xbb, 0x02, 0x00, // mov bx, 2
+        0x93, // xchg ax, bx
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 2);
+    assert_eq!(reg(&cpu, RegIndex::BX), 1);
+
+    // only the low byte of cx is swapped with memory
+    let cpu = run(&[
+        0xc6, 0x06, 0xe8, 0x03, 0x12, // mov byte [1000], 0x12
+        0xb9, 0x34, 0xab, // mov cx, 0xab34
+        0x86, 0x0e, 0xe8, 0x03, // xchg cl, [1000]
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 0xab12);
+    assert_eq!(cpu.read_byte(1000), 0x34);
+    assert_eq!(cpu.read_byte(1001), 0);
+}
+
+#[test]
+fn inc_and_dec_leave_the_carry_alone() {
+    let cpu = run(&[
+        0xb0, 0xff, // mov al, 0xff
+        0x04, 0x01, // add al, 1
+        0x43, // inc bx
+        0x4b, // dec bx
+        0x4b, // dec bx
+    ])
+    .unwrap();
+    assert!(cpu.get_flag(Flag::Carry));
+    assert_eq!(reg(&cpu, RegIndex::BX), 0xffff);
+
+    // and don't set it when they wrap either
+    let cpu = run(&[
+        0xbb, 0xff, 0xff, // mov bx, 0xffff
+        0x43, // inc bx
+    ])
+    .unwrap();
+    assert!(!cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Zero));
+    assert_eq!(reg(&cpu, RegIndex::BX), 0);
+}
+
+// every r/m base gets stored through and loaded back from
+#[test]
+fn every_effective_address_base() {
+    let cpu = run(&[
+        0xbb, 0x00, 0x10, // mov bx, 0x1000
+        0xbd, 0x00, 0x20, // mov bp, 0x2000
+        0xbe, 0x10, 0x00, // mov si, 0x10
+        0xbf, 0x20, 0x00, // mov di, 0x20
+        0xc7, 0x00, 0x01, 0x00, // mov word [bx + si], 1
+        0xc7, 0x01, 0x02, 0x00, // mov word [bx + di], 2
+        0xc7, 0x02, 0x03, 0x00, // mov word [bp + si], 3
+        0xc7, 0x03, 0x04, 0x00, // mov word [bp + di], 4
+        0xc7, 0x04, 0x05, 0x00, // mov word [si], 5
+        0xc7, 0x05, 0x06, 0x00, // mov word [di], 6
+        0xc7, 0x06, 0x00, 0x30, 0x07, 0x00, // mov word [0x3000], 7
+        0xc7, 0x07, 0x08, 0x00, // mov word [bx], 8
+        0xc7, 0x46, 0x04, 0x09, 0x00, // mov word [bp + 4], 9
+        0x03, 0x00, // add ax, [bx + si]
+        0x03, 0x01, // add ax, [bx + di]
+        0x03, 0x02, // add ax, [bp + si]
+        0x03, 0x03, // add ax, [bp + di]
+        0x03, 0x04, // add ax, [si]
+        0x03, 0x05, // add ax, [di]
+        0x03, 0x06, 0x00, 0x30, // add ax, [0x3000]
+        0x03, 0x07, // add ax, [bx]
+        0x03, 0x46, 0x04, // add ax, [bp + 4]
+        0x8b, 0x09, // mov cx, [bx + di]
+        0x8b, 0x14, // mov dx, [si]
+    ])
+    .unwrap();
+    let stored = [
+        0x1010, 0x1020, 0x2010, 0x2020, 0x10, 0x20, 0x3000, 0x1000, 0x2004,
+    ]
+    .map(|addr| cpu.read_word(addr));
+    assert_eq!(stored, [1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    assert_eq!(reg(&cpu, RegIndex::AX), 45);
+    assert_eq!(reg(&cpu, RegIndex::CX), 2);
+    assert_eq!(reg(&cpu, RegIndex::DX), 5);
+}
+
+// cmp sets the flags exactly like sub but doesn't write the result
+#[test]
+fn cmp_is_sub_without_the_write() {
+    let pairs: [(u16, u16); 5] = [(0x0300, 1), (5, 5), (0, 1), (0x8000, 1), (0x7fff, 0xffff)];
+    for (a, b) in pairs {
+        let [a_lo, a_hi] = a.to_le_bytes();
+        let [b_lo, b_hi] = b.to_le_bytes();
+        let with = |op: u8| {
+            run(&[
+                0xb8, a_lo, a_hi, // mov ax, a
+                0xbb, b_lo, b_hi, // mov bx, b
+                op, 0xd8, // sub/cmp ax, bx
+            ])
+            .unwrap()
+        };
+        let sub = with(0x29);
+        let cmp = with(0x39);
+        assert_eq!(
+            format_flags(&sub.flags()),
+            format_flags(&cmp.flags()),
+            "{a:#x} - {b:#x}"
+        );
+        assert_eq!(reg(&sub, RegIndex::AX), a.wrapping_sub(b));
+        assert_eq!(reg(&cmp, RegIndex::AX), a);
+    }
+
+    // 0x02ff has nine bits set but parity only counts the low byte's eight
+    let cpu = run(&[
+        0xb8, 0x00, 0x03, // mov ax, 0x0300
+        0xbb, 0x01, 0x00, // mov bx, 1
+        0x39, 0xd8, // cmp ax, bx
+    ])
+    .unwrap();
+    assert!(cpu.get_flag(Flag::Parity));
+}
+
+#[test]
+fn loop_runs_cx_times() {
+    let cpu = run(&[
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0x43, // inc bx
+        0xe2, 0xfd, // loop $-1
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::BX), 3);
+    assert_eq!(reg(&cpu, RegIndex::CX), 0);
+}
+
+#[test]
+fn jl_loops_until_the_signed_compare_fails() {
+    let cpu = run(&[
+        0xb8, 0xfb, 0xff, // mov ax, -5
+        0x40, // inc ax
+        0x83, 0xf8, 0x05, // cmp ax, 5
+        0x7c, 0xfa, // jl $-4
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 5);
+}
+
+#[test]
+fn push_and_pop_go_through_the_stack() {
+    let cpu = run(&[
+        0xbc, 0x00, 0x01, // mov sp, 256
+        0xb8, 0x01, 0x00, // mov ax, 1
+        0xbb, 0x02, 0x00, // mov bx, 2
+        0x50, // push ax
+        0x53, // push bx
+        0x59, // pop cx
+        0x5a, // pop dx
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 2);
+    assert_eq!(reg(&cpu, RegIndex::DX), 1);
+    assert_eq!(reg(&cpu, RegIndex::SP), 256);
+}
+
+#[test]
+fn popf_restores_what_pushf_saved() {
+    let cpu = run(&[
+        0xbc, 0x00, 0x01, // mov sp, 256
+        0xb8, 0xff, 0x7f, // mov ax, 0x7fff
+        0x40, // inc ax, sets SF, OF and AF
+        0x9c, // pushf
+        0x39, 0xc0, // cmp ax, ax
+        0x9d, // popf
+    ])
+    .unwrap();
+    assert_eq!(format_flags(&cpu.flags()), "PASO");
+    assert_eq!(reg(&cpu, RegIndex::SP), 256);
+}
+
+#[test]
+fn call_returns_past_itself() {
+    let cpu = run(&[
+        0xbc, 0x00, 0x01, // mov sp, 256
+        0xe8, 0x05, 0x00, // call $+8
+        0xbb, 0x01, 0x00, // mov bx, 1
+        0xe3, 0x04, // jcxz $+6, past the end
+        0xb8, 0x02, 0x00, // mov ax, 2
+        0xc3, // ret
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 2);
+    assert_eq!(reg(&cpu, RegIndex::BX), 1);
+    assert_eq!(reg(&cpu, RegIndex::SP), 256);
+    assert_eq!(reg(&cpu, RegIndex::IP), 15);
+    // the return address is still on the stack
+    assert_eq!(cpu.memory()[254..256], [0x06, 0x00]);
+
+    let cpu = run(&[
+        0xbc, 0x00, 0x01, // mov sp, 256
+        0x50, // push ax
+        0xe8, 0x02, 0x00, // call $+5
+        0xe3, 0x03, // jcxz $+5, past the end
+        0xc2, 0x02, 0x00, // ret 2
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::SP), 256);
+    assert_eq!(reg(&cpu, RegIndex::IP), 12);
+}
+
+#[test]
+fn runaway_pushes_hit_the_stack_floor() {
+    let program = [
+        0xbc, 0x00, 0x01, // mov sp, 256
+        0x50, // push ax
+        0x75, 0xfd, // jnz $-1
+    ];
+    let mut cpu = CPU::new();
+    cpu.set_stack_floor(Some(0xf0));
+    let result = run_on(cpu, &program);
+    assert!(matches!(result, Err(ExecError::StackOverflow)));
+
+    // popping past the top of the segment instead of wrapping
+    let mut cpu = CPU::new();
+    cpu.set_stack_floor(Some(0));
+    let result = run_on(
+        cpu,
+        &[
+            0xbc, 0xfe, 0xff, // mov sp, 0xfffe
+            0x58, // pop ax
+        ],
+    );
+    assert!(matches!(result, Err(ExecError::StackUnderflow)));
+}
+
+// bytes go through AH:AL, words through DX:AX
+#[test]
+fn multiplies_and_divides_both_widths() {
+    let cpu = run(&[
+        0xb0, 0xc8, // mov al, 200
+        0xb3, 0x03, // mov bl, 3
+        0xf6, 0xe3, // mul bl
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 600);
+    // the top half isn't 0, so it didn't fit
+    assert!(cpu.get_flag(Flag::Carry) && cpu.get_flag(Flag::Overflow));
+
+    let cpu = run(&[
+        0xb0, 0xfe, // mov al, -2
+        0xb3, 0x03, // mov bl, 3
+        0xf6, 0xeb, // imul bl
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), -6i16 as u16);
+    // it's just the sign extension of al
+    assert!(!cpu.get_flag(Flag::Carry) && !cpu.get_flag(Flag::Overflow));
+
+    let cpu = run(&[
+        0xb8, 0x34, 0x12, // mov ax, 0x1234
+        0xbb, 0x00, 0x01, // mov bx, 0x100
+        0xf7, 0xe3, // mul bx
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::DX), 0x0012);
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x3400);
+
+    let cpu = run(&[
+        0xb8, 0xff, 0xff, // mov ax, -1
+        0xbb, 0x02, 0x00, // mov bx, 2
+        0xf7, 0xeb, // imul bx
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::DX), 0xffff);
+    assert_eq!(reg(&cpu, RegIndex::AX), -2i16 as u16);
+
+    let cpu = run(&[
+        0xb8, 0x58, 0x02, // mov ax, 600
+        0xb3, 0x07, // mov bl, 7
+        0xf6, 0xf3, // div bl
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AL), 85);
+    assert_eq!(reg(&cpu, RegIndex::AH), 5);
+
+    // rounds toward 0, and the remainder takes the dividend's sign
+    let cpu = run(&[
+        0xb8, 0xf9, 0xff, // mov ax, -7
+        0xb3, 0x02, // mov bl, 2
+        0xf6, 0xfb, // idiv bl
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AL), -3i8 as u8 as u16);
+    assert_eq!(reg(&cpu, RegIndex::AH), -1i8 as u8 as u16);
+
+    let cpu = run(&[
+        0xba, 0x01, 0x00, // mov dx, 1
+        0xb8, 0x00, 0x00, // mov ax, 0
+        0xbb, 0x03, 0x00, // mov bx, 3
+        0xf7, 0xf3, // div bx, 0x10000 / 3
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x5555);
+    assert_eq!(reg(&cpu, RegIndex::DX), 1);
+
+    let cpu = run(&[
+        0xba, 0xfe, 0xff, // mov dx, 0xfffe
+        0xb8, 0x60, 0x79, // mov ax, 0x7960, -100000 in dx:ax
+        0xbb, 0x07, 0x00, // mov bx, 7
+        0xf7, 0xfb, // idiv bx
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), -14285i16 as u16);
+    assert_eq!(reg(&cpu, RegIndex::DX), -5i16 as u16);
+}
+
+#[test]
+fn divide_by_zero_is_an_error() {
+    let result = run(&[
+        0xb8, 0x2c, 0x01, // mov ax, 300
+        0xf6, 0xf3, // div bl
+    ]);
+    assert!(matches!(result, Err(ExecError::DivideByZero)));
+}
+
+// -128 fits in a byte, but the 8086 still traps on it
+#[test]
+fn idiv_overflows_on_the_most_negative_quotient() {
+    let result = run(&[
+        0xb8, 0x00, 0xff, // mov ax, -256
+        0xb3, 0x02, // mov bl, 2
+        0xf6, 0xfb, // idiv bl
+    ]);
+    assert!(matches!(result, Err(ExecError::DivideOverflow)));
+
+    let result = run(&[
+        0xba, 0xff, 0xff, // mov dx, -1
+        0xb8, 0x00, 0x00, // mov ax, 0
+        0xbb, 0x02, 0x00, // mov bx, 2
+        0xf7, 0xfb, // idiv bx
+    ]);
+    assert!(matches!(result, Err(ExecError::DivideOverflow)));
+
+    // one more is fine
+    let cpu = run(&[
+        0xb8, 0x02, 0xff, // mov ax, -254
+        0xb3, 0x02, // mov bl, 2
+        0xf6, 0xfb, // idiv bl
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AL), 0x81);
+    assert_eq!(reg(&cpu, RegIndex::AH), 0);
+}
+
+#[test]
+fn exec_reports_the_flag_delta() {
+    let insts = decode(&[
+        0x39, 0xc0, // cmp ax, ax
+        0x83, 0xc0, 0x01, // add ax, 1
+    ])
+    .unwrap();
+    let mut cpu = CPU::new();
+    let deltas = insts
+        .into_iter()
+        .map(|inst| {
+            let size = inst.size();
+            let exec = cpu.exec(inst, size).unwrap();
+            let (old, new) = (&exec.old_flags, &exec.new_flags);
+            format!("{}->{}", format_flags(old), format_flags(new))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(deltas, ["->PZ", "PZ->"]);
+}
+
+// stepping by `size` gets to the next instruction even when it wasn't
+// encoded the way nasm would
+#[test]
+fn size_steps_over_long_encodings() {
+    let program = [
+        0x81, 0xc3, 0x05, 0x00, // add bx, 5
+        0xff, 0xf0, // push ax
+        0x8b, 0x0e, 0x00, 0x01, // mov cx, [256]
+    ];
+    let mut cpu = CPU::new();
+    let mut ip = 0;
+    for inst in decode(&program).unwrap() {
+        ip += inst.size();
+        cpu.exec(inst, inst.size()).unwrap();
+        assert_eq!(cpu.ip() as usize, ip, "{inst}");
+    }
+    assert_eq!(ip, program.len());
+    assert_eq!(reg(&cpu, RegIndex::BX), 5);
+}
+
+#[test]
+fn flags_round_trip_through_the_flags_register() {
+    // cmp ax, ax sets PF and ZF, bits 2 and 6
+    let cpu = run(&[0x39, 0xc0]).unwrap();
+    assert_eq!(flags_to_word(&cpu.flags()), 0x0044);
+    assert_eq!(flags_from_word(0x0044), cpu.flags());
+
+    let all = flags_from_word(0xffff);
+    assert_eq!(format_flags(&all), "CPAZSDO");
+    assert_eq!(flags_to_word(&all), 0x0cd5);
+}
+
+#[test]
+fn flags_round_trip_through_a_string() {
+    let flags = flags_from_string("CZ").unwrap();
+    assert_eq!(format_flags(&flags), "CZ");
+    assert_eq!(flags_from_string("ZC"), Some(flags));
+    assert_eq!(flags_from_string(""), Some(flags_from_word(0)));
+    assert_eq!(flags_from_string("CPAZSDO"), Some(flags_from_word(0xffff)));
+    assert_eq!(flags_from_string("CX"), None);
+}
+
+#[test]
+fn std_and_cld_set_the_direction() {
+    let cpu = run(&[0xfd]).unwrap();
+    assert!(cpu.get_flag(Flag::Direction));
+    assert_eq!(flags_to_word(&cpu.flags()), 0x0400);
+
+    let cpu = run(&[
+        0xfd, // std
+        0xfc, // cld
+    ])
+    .unwrap();
+    assert!(!cpu.get_flag(Flag::Direction));
+
+    // and it survives a trip through the stack
+    let cpu = run(&[
+        0xbc, 0x00, 0x10, // mov sp, 0x1000
+        0xfd, // std
+        0x9c, // pushf
+        0xfc, // cld
+        0x9d, // popf
+    ])
+    .unwrap();
+    assert!(cpu.get_flag(Flag::Direction));
+}
+
+#[test]
+fn rep_stosw_fills_memory() {
+    let cpu = run(&[
+        0xb8, 0xcd, 0xab, // mov ax, 0xabcd
+        0xbf, 0x00, 0x01, // mov di, 0x100
+        0xb9, 0x04, 0x00, // mov cx, 4
+        0xf3, 0xab, // rep stosw
+    ])
+    .unwrap();
+    for addr in (0x100..0x108).step_by(2) {
+        assert_eq!(cpu.read_word(addr), 0xabcd, "{addr:#x}");
+    }
+    assert_eq!(cpu.read_word(0x108), 0);
+    assert_eq!(reg(&cpu, RegIndex::CX), 0);
+    assert_eq!(reg(&cpu, RegIndex::DI), 0x108);
+
+    // with cx at 0 there's nothing to do
+    let cpu = run(&[
+        0xbf, 0x00, 0x01, // mov di, 0x100
+        0xf3, 0xaa, // rep stosb
+    ])
+    .unwrap();
+    assert_eq!(cpu.read_byte(0x100), 0);
+    assert_eq!(reg(&cpu, RegIndex::DI), 0x100);
+}
+
+#[test]
+fn std_makes_string_ops_go_down() {
+    let mut cpu = CPU::new().with_registers(&[
+        (RegIndex::SI, 0x204),
+        (RegIndex::DI, 0x304),
+        (RegIndex::CX, 3),
+    ]);
+    for (addr, val) in [(0x200, 1), (0x202, 2), (0x204, 3)] {
+        cpu.write_word(addr, val);
+    }
+    let cpu = run_on(
+        cpu,
+        &[
+            0xfd, // std
+            0xf3, 0xa5, // rep movsw
+        ],
+    )
+    .unwrap();
+    let words = [0x300, 0x302, 0x304].map(|addr| cpu.read_word(addr));
+    assert_eq!(words, [1, 2, 3]);
+    assert_eq!(cpu.read_word(0x2fe), 0);
+    assert_eq!(reg(&cpu, RegIndex::SI), 0x1fe);
+    assert_eq!(reg(&cpu, RegIndex::DI), 0x2fe);
+    assert_eq!(reg(&cpu, RegIndex::CX), 0);
+}
+
+#[test]
+fn segment_override_moves_the_string_source() {
+    let mut cpu = CPU::new_segmented().with_registers(&[
+        (RegIndex::ES, 0x100),
+        (RegIndex::DI, 0x10),
+        (RegIndex::CX, 2),
+    ]);
+    cpu.write_byte(0x1000, b'h');
+    cpu.write_byte(0x1001, b'i');
+    let cpu = run_on(
+        cpu,
+        &[
+            0x26, 0xf3, 0xa4, // rep es movsb
+        ],
+    )
+    .unwrap();
+    // from es:si rather than ds:si, which is all zeros
+    assert_eq!(cpu.read_byte(0x1010), b'h');
+    assert_eq!(cpu.read_byte(0x1011), b'i');
+}
+
+#[test]
+fn pokes_reach_all_of_segmented_memory() {
+    let mut cpu = CPU::new_segmented().with_registers(&[(RegIndex::DS, 0xf000)]);
+    let addr = physical_address(0xf000, 0x1234) as u32;
+    cpu.write_word(addr, 0xabcd);
+    let mut cpu = run_on(
+        cpu,
+        &[
+            0x8b, 0x0e, 0x34, 0x12, // mov cx, [0x1234]
+            0xc7, 0x06, 0xff, 0xff, 0x78, 0x56, // mov word [0xffff], 0x5678
+        ],
+    )
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 0xabcd);
+    assert_eq!(cpu.read_word(addr), 0xabcd);
+    // the sim wraps at the end of the segment
+    assert_eq!(cpu.read_byte(0xfffff), 0x78);
+    assert_eq!(cpu.read_byte(0xf0000), 0x56);
+
+    // and a poke at the end of memory, back to the start
+    cpu.write_word(0xfffff, 0x1234);
+    assert_eq!(cpu.read_byte(0), 0x12);
+    assert_eq!(cpu.read_word(0xfffff), 0x1234);
+}
+
+#[test]
+fn repne_and_repe_stop_on_the_compare() {
+    let mut cpu = CPU::new().with_registers(&[
+        (RegIndex::AL, b'l' as u16),
+        (RegIndex::DI, 0x100),
+        (RegIndex::CX, 5),
+    ]);
+    for (i, b) in b"hello".iter().enumerate() {
+        cpu.write_byte(0x100 + i as u32, *b);
+    }
+    // repne scasb, looking for the first l
+    let cpu = run_on(cpu, &[0xf2, 0xae]).unwrap();
+    assert_eq!(reg(&cpu, RegIndex::DI), 0x103);
+    assert_eq!(reg(&cpu, RegIndex::CX), 2);
+    assert!(cpu.get_flag(Flag::Zero));
+
+    let mut cpu = CPU::new().with_registers(&[
+        (RegIndex::SI, 0x100),
+        (RegIndex::DI, 0x200),
+        (RegIndex::CX, 3),
+    ]);
+    for (i, (a, b)) in b"abc".iter().zip(b"axc").enumerate() {
+        cpu.write_byte(0x100 + i as u32, *a);
+        cpu.write_byte(0x200 + i as u32, *b);
+    }
+    // repe cmpsb, which gets as far as the x
+    let cpu = run_on(cpu, &[0xf3, 0xa6]).unwrap();
+    assert_eq!(reg(&cpu, RegIndex::SI), 0x102);
+    assert_eq!(reg(&cpu, RegIndex::CX), 1);
+    assert!(!cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn lods_loads_the_accumulator() {
+    let mut cpu = CPU::new().with_registers(&[(RegIndex::SI, 0x100)]);
+    cpu.write_word(0x100, 0x1234);
+    let cpu = run_on(
+        cpu,
+        &[
+            0xac, // lodsb
+            0xad, // lodsw, from 0x101
+        ],
+    )
+    .unwrap();
+    // 0x34 went into al first, then the word at 0x101 over all of ax
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x0012);
+    assert_eq!(reg(&cpu, RegIndex::SI), 0x103);
+}
+
+#[test]
+fn renders_past_the_end_of_memory_as_black() {
+    let mut cpu = CPU::new();
+    cpu.write_word(0xfff8, 0x0201);
+    cpu.write_word(0xfffa, 0xff03);
+    let image = ppm(cpu.memory(), 0xfff8, 2, 2);
+    let (header, pixels) = image.split_at(b"P6\n2 2\n255\n".len());
+    assert_eq!(header, b"P6\n2 2\n255\n");
+    assert_eq!(pixels, [1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn stats_count_what_ran() {
+    let program = [
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0xb8, 0x01, 0x00, // mov ax, 1
+        0x83, 0xe9, 0x01, // sub cx, 1
+        0x75, 0xf8, // jnz back to the mov ax
+    ];
+    let mut cpu = CPU::new();
+    let mut stats = Stats::default();
+    while (cpu.ip() as usize) < program.len() {
+        let (inst, num_bytes) = decode_first_at(&program, cpu.ip() as usize).unwrap();
+        stats.record(&inst, &cpu);
+        cpu.exec(inst, num_bytes).unwrap();
+    }
+    assert_eq!(stats.executed, 10);
+    assert_eq!(stats.count("mov"), 4);
+    assert_eq!(stats.count("jnz"), 3);
+    assert_eq!((stats.taken, stats.not_taken), (2, 1));
+    // a loop's jump is taken every time round but the last
+    let jnz = Branch {
+        evaluated: 3,
+        taken: 2,
+    };
+    assert_eq!(stats.branches(), [(9, jnz)]);
+    assert_eq!(stats.histogram(), [("mov", 4), ("jnz", 3), ("sub", 3)]);
+}
+
+// the rep's just a prefix, it's the string op that ran
+#[test]
+fn stats_count_rep_strings_by_their_op() {
+    let program = [
+        0xb9, 0x02, 0x00, // mov cx, 2
+        0xf3, 0xa4, // rep movsb
+        0xab, // stosw
+    ];
+    let mut cpu = CPU::new();
+    let mut stats = Stats::default();
+    while (cpu.ip() as usize) < program.len() {
+        let (inst, num_bytes) = decode_first_at(&program, cpu.ip() as usize).unwrap();
+        stats.record(&inst, &cpu);
+        cpu.exec(inst, num_bytes).unwrap();
+    }
+    assert_eq!(stats.histogram(), [("mov", 1), ("movsb", 1), ("stosw", 1)]);
+    assert_eq!(stats.count("rep"), 0);
+}
+
+#[test]
+fn hlt_stops_before_whatever_follows() {
+    let cpu = run(&[
+        0xb9, 0x03, 0x00, // mov cx, 3
+        0xf4, // hlt
+        0x0f, // not an instruction
+        0xb9, 0x04, 0x00, // mov cx, 4
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 3);
+    assert_eq!(cpu.ip(), 4);
+}
+
+#[test]
+fn starts_from_the_registers_it_was_given() {
+    let mut cpu = CPU::new().with_registers(&[(RegIndex::BX, 1000), (RegIndex::CL, 7)]);
+    let program = [
+        0x88, 0x0f, // mov [bx], cl
+    ];
+    let (inst, num_bytes) = decode_first_at(&program, 0).unwrap();
+    cpu.exec(inst, num_bytes).unwrap();
+    assert_eq!(cpu.memory()[1000], 7);
+
+    cpu.reset(false);
+    assert_eq!(reg(&cpu, RegIndex::BX), 0);
+    assert_eq!(cpu.ip(), 0);
+    assert_eq!(cpu.memory()[1000], 7);
+    cpu.reset(true);
+    assert_eq!(cpu.memory()[1000], 0);
+}
+
+#[test]
+fn restoring_a_snapshot_undoes_what_ran_since() {
+    let program = [
+        0xbb, 0xe8, 0x03, // mov bx, 1000
+        0xc6, 0x07, 0x2a, // mov byte [bx], 42
+        0x83, 0xeb, 0x01, // sub bx, 1
+    ];
+    let mut cpu = CPU::new().with_registers(&[(RegIndex::CX, 7)]);
+    let regs_only = cpu.snapshot();
+    let full = cpu.snapshot_full();
+    cpu = run_on(cpu, &program).unwrap();
+    assert_eq!(cpu.memory()[1000], 42);
+    assert_ne!(cpu.snapshot(), regs_only);
+
+    cpu.restore(&regs_only);
+    assert_eq!(cpu.snapshot(), regs_only);
+    assert_eq!(reg(&cpu, RegIndex::CX), 7);
+    assert_eq!(cpu.memory()[1000], 42);
+
+    cpu.restore(&full);
+    assert_eq!(cpu.snapshot_full(), full);
+    assert_eq!(cpu.memory()[1000], 0);
+}
+
+#[test]
+fn poked_words_are_little_endian() {
+    let mut cpu = CPU::new();
+    cpu.write_word(1000, 0x1234);
+    assert_eq!(cpu.read_byte(1000), 0x34);
+    assert_eq!(cpu.read_byte(1001), 0x12);
+    assert_eq!(cpu.read_word(1000), 0x1234);
+
+    cpu.write_byte(1001, 0xab);
+    assert_eq!(cpu.read_word(1000), 0xab34);
+
+    // and the sim sees them
+    let cpu = run_on(
+        cpu.with_registers(&[(RegIndex::BX, 1000)]),
+        &[
+            0x8b, 0x0f, // mov cx, [bx]
+        ],
+    )
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 0xab34);
+}
+
+#[test]
+fn calls_and_jumps_through_registers_and_memory() {
+    let cpu = run_on(
+        CPU::new().with_registers(&[(RegIndex::SP, 0x100)]),
+        &[
+            0xb8, 0x06, 0x00, // mov ax, 6
+            0xff, 0xd0, // call ax
+            0xf4, // hlt
+            0xb9, 0x01, 0x00, // mov cx, 1
+            0xc3, // ret
+        ],
+    )
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CX), 1);
+    assert_eq!(reg(&cpu, RegIndex::SP), 0x100);
+    assert_eq!(cpu.ip(), 6);
+
+    // a far pointer is the new ip, then the new cs
+    let mut cpu = CPU::new_segmented();
+    cpu.write_word(1000, 6);
+    cpu.write_word(1002, 0x1234);
+    let cpu = run_on(
+        cpu,
+        &[
+            0xbb, 0xe8, 0x03, // mov bx, 1000
+            0xff, 0x2f, // jmp far [bx]
+            0xf4, // hlt, jumped over
+            0xf4, // hlt
+        ],
+    )
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::CS), 0x1234);
+    assert_eq!(cpu.ip(), 7);
+}
+
+#[test]
+fn ports_read_as_zero() {
+    let cpu = run_on(
+        CPU::new().with_registers(&[(RegIndex::AX, 0x1234)]),
+        &[
+            0xe6, 0x3c, // out 60, al
+            0xe4, 0x3c, // in al, 60
+        ],
+    )
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x1200);
+}
+
+#[test]
+fn bcd_adjusts_fix_up_al() {
+    let cpu = run(&[
+        0xb0, 0x38, // mov al, 0x38
+        0x04, 0x45, // add al, 0x45
+        0x27, // daa, 38 + 45 = 83
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AL), 0x83);
+
+    let cpu = run(&[
+        0xb0, 0x83, // mov al, 0x83
+        0x2c, 0x45, // sub al, 0x45
+        0x2f, // das, 83 - 45 = 38
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AL), 0x38);
+
+    let cpu = run(&[
+        0xb8, 0x09, 0x00, // mov ax, 9
+        0x04, 0x08, // add al, 8
+        0x37, // aaa, 9 + 8 = 17
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 0x0107);
+    assert!(cpu.get_flag(Flag::Carry));
+
+    let cpu = run(&[
+        0xb0, 0x07, // mov al, 7
+        0xb3, 0x09, // mov bl, 9
+        0xf6, 0xe3, // mul bl
+        0xd4, 0x0a, // aam, 7 * 9 = 63
+        0xd5, 0x0a, // aad, and back
+    ])
+    .unwrap();
+    assert_eq!(reg(&cpu, RegIndex::AX), 63);
+}
@@ -0,0 +1,32 @@
+use std::process::Command;
+
+// everything after the `;` on each instruction line, the asm itself is
+// checked by the decode tests
+fn trace_of(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(" ; "))
+        .map(|(_, trace)| trace.trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn trace_matches_the_reference() {
+    for name in [
+        "listing_0048_ip_register",
+        "listing_0049_conditional_jumps",
+        "listing_0052_memory_add_loop",
+    ] {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+        let output = Command::new(env!("CARGO_BIN_EXE_sim"))
+            .arg(format!("{dir}/{name}"))
+            .args(["-exec", "-trace"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{name}");
+
+        let got = trace_of(&String::from_utf8(output.stdout).unwrap());
+        let want = trace_of(&std::fs::read_to_string(format!("{dir}/{name}.txt")).unwrap());
+        assert_eq!(got, want, "{name}");
+    }
+}
@@ -35,9 +35,9 @@ puts "running #{DECODER_LISTINGS.size} decoder tests..."
 DECODER_LISTINGS.each do |listing|
   Tempfile.create do |new_listing_file|
     Tempfile.create do |new_output_file|
-      # decode the binary and put it in new_listing_file
-      new_listing_file.write(run(listing))
+      # decode the binary into new_listing_file
       new_listing_file.close
+      system("./sim/target/debug/sim #{listing} -o #{new_listing_file.path}")
 
       `nasm #{new_listing_file.path} -o #{new_output_file.path}`
       if readbin(new_output_file.path) == readbin(listing)
@@ -58,15 +58,15 @@ SIM_LISTINGS = [
   "listing_0044_register_movs",
   # "listing_0045_challenge_register_movs",
   "listing_0046_add_sub_cmp",
+  "listing_0047_challenge_flags",
   "listing_0048_ip_register",
   "listing_0049_conditional_jumps",
-  # we might as well try this one, looks not that bad:
-  # "listing_0050_challenge_jumps", 
+  "listing_0050_challenge_jumps",
   "listing_0051_memory_mov",
   "listing_0052_memory_add_loop",
   "listing_0053_add_loop_challenge",
   "listing_0054_draw_rectangle",
-  # "listing_0055_challenge_rectangle",
+  "listing_0055_challenge_rectangle",
 ]
 
 State = Struct.new(:regs, :flags)